@@ -1,6 +1,28 @@
 #![feature(test)]
 extern crate test;
 
+#[cfg(test)]
+mod attacks {
+    use shogi_core::Square;
+    use test::Bencher;
+    use yasai::attacks::{bishop_attacks, lance_attacks, rook_attacks};
+    use yasai::Bitboard;
+
+    #[bench]
+    fn bench_sliding_attacks(b: &mut Bencher) {
+        let occ = [Square::SQ_3C, Square::SQ_5E, Square::SQ_7G, Square::SQ_2H]
+            .into_iter()
+            .fold(Bitboard::empty(), |bb, sq| bb | Bitboard::single(sq));
+        b.iter(|| {
+            Square::all().fold(0, |acc, sq| {
+                acc + bishop_attacks(sq, &occ).count()
+                    + rook_attacks(sq, &occ).count()
+                    + lance_attacks(shogi_core::Color::Black, sq, &occ).count()
+            })
+        });
+    }
+}
+
 #[cfg(test)]
 mod movegen {
     use shogi_core::PartialPosition;
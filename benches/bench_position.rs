@@ -0,0 +1,84 @@
+#![feature(test)]
+extern crate test;
+
+#[cfg(test)]
+mod clone {
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+    use test::Bencher;
+    use yasai::Position;
+
+    /// Plays random legal moves from the default position.
+    fn random_game(plies: usize) -> Position {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut pos = Position::default();
+        for _ in 0..plies {
+            match pos.legal_moves().choose(&mut rng) {
+                Some(&m) => pos.do_move(m),
+                None => break,
+            }
+        }
+        pos
+    }
+
+    #[bench]
+    fn bench_clone_after_200_plies(b: &mut Bencher) {
+        let pos = random_game(200);
+        b.iter(|| pos.clone());
+    }
+
+    #[bench]
+    fn bench_clone_shallow_after_200_plies(b: &mut Bencher) {
+        let pos = random_game(200);
+        b.iter(|| pos.clone_shallow());
+    }
+}
+
+#[cfg(test)]
+mod history {
+    use shogi_core::{Color, Piece, Square};
+    use test::Bencher;
+    use yasai::{Position, PositionBuilder};
+
+    fn position(track: bool) -> Position {
+        PositionBuilder::new()
+            .place(Square::SQ_5I, Piece::B_K)
+            .place(Square::SQ_5A, Piece::W_K)
+            .place(Square::SQ_2H, Piece::B_R)
+            .place(Square::SQ_8B, Piece::W_R)
+            .side_to_move(Color::Black)
+            .track_history(track)
+            .build()
+            .expect("failed to build")
+    }
+
+    /// Plays the first legal move down to `depth` plies, then undoes them all.
+    fn deep_do_undo(pos: &mut Position, depth: usize) {
+        let mut moves = Vec::with_capacity(depth);
+        for _ in 0..depth {
+            match pos.legal_moves().first() {
+                Some(&m) => {
+                    pos.do_move(m);
+                    moves.push(m);
+                }
+                None => break,
+            }
+        }
+        for m in moves.into_iter().rev() {
+            pos.undo_move(m);
+        }
+    }
+
+    #[bench]
+    fn bench_deep_do_undo_with_history(b: &mut Bencher) {
+        let mut pos = position(true);
+        b.iter(|| deep_do_undo(&mut pos, 64));
+    }
+
+    #[bench]
+    fn bench_deep_do_undo_without_history(b: &mut Bencher) {
+        let mut pos = position(false);
+        b.iter(|| deep_do_undo(&mut pos, 64));
+    }
+}
@@ -0,0 +1,125 @@
+//! Attacks of each piece under a given occupancy.
+
+use crate::bitboard::Bitboard;
+use crate::tables::ATTACK_TABLE;
+use shogi_core::{Color, PieceKind, Square};
+
+/// Returns the squares attacked by a piece of `pk` and `c` on `sq` under the occupancy `occ`.
+///
+/// The helpers below take the color first where the attacks depend on it, then the square,
+/// then the occupancy for the sliding pieces.
+#[inline(always)]
+pub fn attacks_from(pk: PieceKind, c: Color, sq: Square, occ: &Bitboard) -> Bitboard {
+    ATTACK_TABLE.attack(pk, sq, c, occ)
+}
+
+/// Returns the squares attacked by a pawn of `c` on `sq`: the square in front of it from `c`'s side.
+#[inline(always)]
+pub fn pawn_attacks(c: Color, sq: Square) -> Bitboard {
+    ATTACK_TABLE.fu.attack(sq, c)
+}
+
+/// Returns the squares attacked by a lance of `c` on `sq` under the occupancy `occ`,
+/// up to and including the first occupied square in front of it from `c`'s side.
+#[inline(always)]
+pub fn lance_attacks(c: Color, sq: Square, occ: &Bitboard) -> Bitboard {
+    ATTACK_TABLE.ky.attack(sq, c, occ)
+}
+
+/// Returns the squares attacked by a knight of `c` on `sq`, two ranks ahead from `c`'s side.
+#[inline(always)]
+pub fn knight_attacks(c: Color, sq: Square) -> Bitboard {
+    ATTACK_TABLE.ke.attack(sq, c)
+}
+
+/// Returns the squares attacked by a silver of `c` on `sq`, oriented from `c`'s side.
+#[inline(always)]
+pub fn silver_attacks(c: Color, sq: Square) -> Bitboard {
+    ATTACK_TABLE.gi.attack(sq, c)
+}
+
+/// Returns the squares attacked by a gold of `c` on `sq`, oriented from `c`'s side.
+///
+/// Promoted pawns, lances, knights and silvers attack the same squares.
+#[inline(always)]
+pub fn gold_attacks(c: Color, sq: Square) -> Bitboard {
+    ATTACK_TABLE.ki.attack(sq, c)
+}
+
+/// Returns the squares attacked by a bishop on `sq` under the occupancy `occ`,
+/// up to and including the first occupied square in each direction. The same for both colors.
+#[inline(always)]
+pub fn bishop_attacks(sq: Square, occ: &Bitboard) -> Bitboard {
+    ATTACK_TABLE.ka.attack(sq, occ)
+}
+
+/// Returns the squares attacked by a rook on `sq` under the occupancy `occ`,
+/// up to and including the first occupied square in each direction. The same for both colors.
+#[inline(always)]
+pub fn rook_attacks(sq: Square, occ: &Bitboard) -> Bitboard {
+    ATTACK_TABLE.hi.attack(sq, occ)
+}
+
+/// Returns the squares attacked by a king on `sq`. The same for both colors.
+#[inline(always)]
+pub fn king_attacks(sq: Square) -> Bitboard {
+    ATTACK_TABLE.ou.attack(sq, Color::Black)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shogi_core::consts::square::*;
+
+    fn bb(squares: &[Square]) -> Bitboard {
+        squares
+            .iter()
+            .fold(Bitboard::empty(), |bb, &sq| bb | Bitboard::single(sq))
+    }
+
+    #[test]
+    fn step_attacks() {
+        assert_eq!(bb(&[]), pawn_attacks(Color::Black, SQ_1A));
+        assert_eq!(bb(&[SQ_9H]), pawn_attacks(Color::Black, SQ_9I));
+        assert_eq!(bb(&[SQ_1B]), pawn_attacks(Color::White, SQ_1A));
+        assert_eq!(bb(&[SQ_2A]), knight_attacks(Color::Black, SQ_1C));
+        assert_eq!(bb(&[]), knight_attacks(Color::Black, SQ_1B));
+        assert_eq!(bb(&[SQ_8G]), knight_attacks(Color::White, SQ_9E));
+        assert_eq!(bb(&[SQ_4H, SQ_6H]), silver_attacks(Color::White, SQ_5I));
+        assert_eq!(
+            bb(&[SQ_1A, SQ_2A, SQ_2C]),
+            silver_attacks(Color::Black, SQ_1B)
+        );
+        assert_eq!(
+            bb(&[SQ_8H, SQ_8I, SQ_9H]),
+            gold_attacks(Color::Black, SQ_9I)
+        );
+        assert_eq!(bb(&[SQ_8I, SQ_9H]), gold_attacks(Color::White, SQ_9I));
+        assert_eq!(bb(&[SQ_1B, SQ_2A, SQ_2B]), king_attacks(SQ_1A));
+        assert_eq!(bb(&[SQ_8H, SQ_8I, SQ_9H]), king_attacks(SQ_9I));
+    }
+
+    #[test]
+    fn sliding_attacks() {
+        let occ = bb(&[SQ_1C, SQ_4A, SQ_3C]);
+        assert_eq!(
+            bb(&[SQ_1B, SQ_1C, SQ_2A, SQ_3A, SQ_4A]),
+            rook_attacks(SQ_1A, &occ)
+        );
+        assert_eq!(bb(&[SQ_2B, SQ_3C]), bishop_attacks(SQ_1A, &occ));
+        assert_eq!(
+            bb(&[SQ_1C, SQ_1D, SQ_1E, SQ_1F, SQ_1G, SQ_1H]),
+            lance_attacks(Color::Black, SQ_1I, &occ)
+        );
+        assert_eq!(
+            bb(&[SQ_1B, SQ_1C]),
+            lance_attacks(Color::White, SQ_1A, &occ)
+        );
+        assert_eq!(16, bishop_attacks(SQ_5E, &Bitboard::empty()).count());
+        assert_eq!(16, rook_attacks(SQ_5E, &Bitboard::empty()).count());
+        assert_eq!(
+            rook_attacks(SQ_5E, &occ) | king_attacks(SQ_5E),
+            attacks_from(PieceKind::ProRook, Color::White, SQ_5E, &occ)
+        );
+    }
+}
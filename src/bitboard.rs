@@ -1,3 +1,7 @@
+use shogi_core::Square;
+use std::fmt;
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
+
 pub(crate) trait Occupied
 where
     Self: Sized,
@@ -8,7 +12,6 @@ where
     fn sliding_negative_consecutive(&self, mask: &Self) -> Self;
     fn sliding_positives(&self, masks: &[Self; 2]) -> Self;
     fn sliding_negatives(&self, masks: &[Self; 2]) -> Self;
-    fn vacant_files(&self) -> Self;
 }
 
 #[allow(unused_macros)]
@@ -82,24 +85,199 @@ cfg_if::cfg_if! {
         target_feature = "avx2"
     ))] {
         mod x86_64;
-        pub(crate) use self::x86_64::Bitboard;
+        use self::x86_64::Bitboard as Inner;
     } else if #[cfg(all(
         feature = "simd",
         target_arch = "aarch64",
         target_feature = "neon"
     ))] {
         mod aarch64;
-        pub(crate) use self::aarch64::Bitboard;
+        use self::aarch64::Bitboard as Inner;
     } else if #[cfg(all(
         feature = "simd",
         target_arch = "wasm32",
         target_feature = "simd128"
     ))] {
         mod wasm32;
-        pub(crate) use self::wasm32::Bitboard;
+        use self::wasm32::Bitboard as Inner;
     } else {
         mod core;
-        pub(crate) use self::core::Bitboard;
+        use self::core::Bitboard as Inner;
+    }
+}
+
+/// A set of squares.
+///
+/// The representation depends on the `simd` feature and the target, but the API doesn't.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Bitboard(Inner);
+
+impl Bitboard {
+    /// Returns the empty set.
+    #[inline(always)]
+    pub fn empty() -> Self {
+        Self(Inner::empty())
+    }
+    /// Returns the set of `square` only.
+    #[inline(always)]
+    pub fn single(square: Square) -> Self {
+        Self(Inner::single(square))
+    }
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    #[inline(always)]
+    pub fn contains(&self, square: Square) -> bool {
+        self.0.contains(square)
+    }
+    /// Returns the number of squares.
+    #[inline(always)]
+    pub fn count(self) -> u8 {
+        self.0.count()
+    }
+    /// Same as [`Bitboard::count`], named after the integer method.
+    #[inline(always)]
+    pub fn count_ones(self) -> u32 {
+        u32::from(self.count())
+    }
+    /// Returns the squares on the file of 0-based index `f`,
+    /// i.e. the squares whose [`Square::file`] is `f + 1`.
+    ///
+    /// Panics if `f` is not in `0..9`.
+    #[inline(always)]
+    pub fn file(f: u8) -> Self {
+        debug_assert!(f < 9, "file index out of range: {f}");
+        crate::tables::FILES[f as usize]
+    }
+    /// Returns the squares on the rank of 0-based index `r`,
+    /// i.e. the squares whose [`Square::rank`] is `r + 1`.
+    ///
+    /// Panics if `r` is not in `0..9`.
+    #[inline(always)]
+    pub fn rank(r: u8) -> Self {
+        debug_assert!(r < 9, "rank index out of range: {r}");
+        crate::tables::RANKS[r as usize]
+    }
+}
+
+impl Eq for Bitboard {}
+
+impl fmt::Debug for Bitboard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(*self).finish()
+    }
+}
+
+impl Occupied for Bitboard {
+    #[inline(always)]
+    fn shl(&self) -> Self {
+        Self(self.0.shl())
+    }
+    #[inline(always)]
+    fn shr(&self) -> Self {
+        Self(self.0.shr())
+    }
+    #[inline(always)]
+    fn sliding_positive_consecutive(&self, mask: &Self) -> Self {
+        Self(self.0.sliding_positive_consecutive(&mask.0))
+    }
+    #[inline(always)]
+    fn sliding_negative_consecutive(&self, mask: &Self) -> Self {
+        Self(self.0.sliding_negative_consecutive(&mask.0))
+    }
+    #[inline(always)]
+    fn sliding_positives(&self, masks: &[Self; 2]) -> Self {
+        Self(self.0.sliding_positives(&[masks[0].0, masks[1].0]))
+    }
+    #[inline(always)]
+    fn sliding_negatives(&self, masks: &[Self; 2]) -> Self {
+        Self(self.0.sliding_negatives(&[masks[0].0, masks[1].0]))
+    }
+}
+
+macro_rules! forward_bit_trait {
+    (
+        target_trait => $trait:ident, assign_trait => $assign_trait:ident,
+        target_func  => $func:ident,  assign_func  => $assign_func:ident
+    ) => {
+        impl $trait for Bitboard {
+            type Output = Bitboard;
+
+            #[inline(always)]
+            fn $func(self, rhs: Self) -> Self::Output {
+                Self(self.0.$func(rhs.0))
+            }
+        }
+        impl $trait<&Bitboard> for Bitboard {
+            type Output = Bitboard;
+
+            #[inline(always)]
+            fn $func(self, rhs: &Self) -> Self::Output {
+                Self(self.0.$func(rhs.0))
+            }
+        }
+        impl $assign_trait for Bitboard {
+            #[inline(always)]
+            fn $assign_func(&mut self, rhs: Self) {
+                self.0.$assign_func(rhs.0)
+            }
+        }
+    };
+}
+
+forward_bit_trait!(
+    target_trait => BitAnd, assign_trait => BitAndAssign,
+    target_func => bitand, assign_func => bitand_assign
+);
+
+forward_bit_trait!(
+    target_trait => BitOr, assign_trait => BitOrAssign,
+    target_func => bitor, assign_func => bitor_assign
+);
+
+forward_bit_trait!(
+    target_trait => BitXor, assign_trait => BitXorAssign,
+    target_func => bitxor, assign_func => bitxor_assign
+);
+
+impl Not for Bitboard {
+    type Output = Bitboard;
+
+    #[inline(always)]
+    fn not(self) -> Self::Output {
+        Self(!self.0)
+    }
+}
+
+impl Not for &Bitboard {
+    type Output = Bitboard;
+
+    #[inline(always)]
+    fn not(self) -> Self::Output {
+        Bitboard(!self.0)
+    }
+}
+
+/// Iterator over the squares of a [`Bitboard`], in the order of [`Square::array_index`].
+pub struct SquareIterator(<Inner as IntoIterator>::IntoIter);
+
+impl Iterator for SquareIterator {
+    type Item = Square;
+
+    #[inline(always)]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+impl IntoIterator for Bitboard {
+    type Item = Square;
+    type IntoIter = SquareIterator;
+
+    #[inline(always)]
+    fn into_iter(self) -> Self::IntoIter {
+        SquareIterator(self.0.into_iter())
     }
 }
 
@@ -128,6 +306,16 @@ mod tests {
         }
     }
 
+    #[test]
+    fn into_iter() {
+        let bb = Bitboard::single(SQ_9I) | Bitboard::single(SQ_1A) | Bitboard::single(SQ_2B);
+        assert_eq!(
+            vec![SQ_1A, SQ_2B, SQ_9I],
+            bb.into_iter().collect::<Vec<_>>()
+        );
+        assert_eq!(None, Bitboard::empty().into_iter().next());
+    }
+
     #[test]
     fn bit_ops() {
         let bb0 = Bitboard::empty();
@@ -146,6 +334,32 @@ mod tests {
         assert_eq!(bb0, bb);
     }
 
+    #[test]
+    fn file_rank() {
+        assert_eq!(9, Bitboard::file(0).count_ones());
+        for i in 0..9 {
+            assert_eq!(9, Bitboard::file(i).count());
+            assert_eq!(9, Bitboard::rank(i).count());
+        }
+        for sq in Square::all() {
+            let (file, rank) = (Bitboard::file(sq.file() - 1), Bitboard::rank(sq.rank() - 1));
+            assert!(file.contains(sq));
+            assert!(rank.contains(sq));
+            assert_eq!(Bitboard::single(sq), file & rank);
+        }
+        // 5筋の歩
+        let pos = crate::Position::default();
+        let pawns = pos.piece_bitboard(shogi_core::Piece::B_P);
+        assert_eq!(Bitboard::single(SQ_5G), pawns & Bitboard::file(4));
+        assert!((pawns & Bitboard::rank(2)).is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn file_out_of_range() {
+        Bitboard::file(9);
+    }
+
     #[test]
     fn shift() {
         assert_eq!(Bitboard::single(SQ_1B), Bitboard::single(SQ_1A).shl());
@@ -133,14 +133,6 @@ impl Occupied for Bitboard {
     fn sliding_negatives(&self, masks: &[Self; 2]) -> Self {
         self.sliding_negative(&masks[0]) | self.sliding_negative(&masks[1])
     }
-    fn vacant_files(&self) -> Self {
-        unsafe {
-            let mask = aarch64::vld1q_u64([0x4020_1008_0402_0100, 0x0002_0100].as_ptr());
-            let sub = aarch64::vsubq_u64(mask, self.0);
-            let shr = aarch64::vshrq_n_u64::<8>(aarch64::vandq_u64(sub, mask));
-            Self(aarch64::veorq_u64(mask, aarch64::vsubq_u64(mask, shr)))
-        }
-    }
 }
 
 define_bit_trait_unsafe!(
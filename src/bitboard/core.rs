@@ -2,8 +2,6 @@ use super::Occupied;
 pub(crate) use shogi_core::Bitboard;
 use shogi_core::Square;
 
-const VACANT_MASK_VALUE: u128 = 0x0002_0100_4020_1008_0402_0100;
-const VACANT_MASK: Bitboard = unsafe { Bitboard::from_u128_unchecked(VACANT_MASK_VALUE) };
 const BB_1A: Bitboard = Bitboard::single(Square::SQ_1A);
 const BB_9I: Bitboard = Bitboard::single(Square::SQ_9I);
 
@@ -55,10 +53,4 @@ impl Occupied for Bitboard {
     fn sliding_negatives(&self, masks: &[Self; 2]) -> Self {
         sliding_negative(self, &masks[0]) | sliding_negative(self, &masks[1])
     }
-    #[inline(always)]
-    fn vacant_files(&self) -> Self {
-        let bb = unsafe { Self::from_u128_unchecked(VACANT_MASK_VALUE - self.to_u128()) };
-        VACANT_MASK
-            ^ unsafe { Self::from_u128_unchecked(VACANT_MASK_VALUE - bb.shift_up(8).to_u128()) }
-    }
 }
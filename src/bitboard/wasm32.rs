@@ -115,13 +115,6 @@ impl Occupied for Bitboard {
     fn sliding_negatives(&self, masks: &[Self; 2]) -> Self {
         self.sliding_negative(&masks[0]) | self.sliding_negative(&masks[1])
     }
-    fn vacant_files(&self) -> Self {
-        let mask = wasm32::u64x2(0x4020_1008_0402_0100, 0x0002_0100);
-        let sub = wasm32::u64x2_sub(mask, self.0);
-        let and = wasm32::v128_and(sub, mask);
-        let shr = wasm32::u64x2_shr(and, 8);
-        Self(wasm32::v128_xor(mask, wasm32::u64x2_sub(mask, shr)))
-    }
 }
 
 define_bit_trait!(
@@ -151,17 +151,6 @@ impl Occupied for Bitboard {
             ))
         }
     }
-    fn vacant_files(&self) -> Self {
-        unsafe {
-            let mask = x86_64::_mm_set_epi64x(0x0002_0100, 0x4020_1008_0402_0100);
-            let sub = x86_64::_mm_sub_epi64(mask, self.0);
-            let shr = x86_64::_mm_srli_epi64::<8>(x86_64::_mm_and_si128(mask, sub));
-            Self(x86_64::_mm_xor_si128(
-                mask,
-                x86_64::_mm_sub_epi64(mask, shr),
-            ))
-        }
-    }
 }
 
 define_bit_trait_unsafe!(
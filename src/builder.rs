@@ -0,0 +1,295 @@
+use crate::hand::{count_pieces, HAND_PIECE_NUMS};
+use crate::{PieceExt, Position, Rules};
+use shogi_core::{Color, Hand, PartialPosition, Piece, PieceKind, Square};
+use std::fmt;
+
+/// An error that can occur while building a [`Position`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionError {
+    /// The piece kind can't be in hand.
+    InvalidHandPiece(PieceKind),
+    /// There are more pieces of the kind than a full set has.
+    TooManyPieces(PieceKind),
+    /// The piece on the square can never move.
+    StuckPiece(Square),
+    /// Two unpromoted pawns of the color are on the same file (二歩).
+    DoublePawns(Color),
+    /// The side not to move is in check.
+    OpponentInCheck,
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PositionError::InvalidHandPiece(pk) => write!(f, "{pk:?} can't be in hand"),
+            PositionError::TooManyPieces(pk) => write!(f, "too many pieces of {pk:?}"),
+            PositionError::StuckPiece(sq) => write!(f, "stuck piece at {sq:?}"),
+            PositionError::DoublePawns(c) => write!(f, "double pawns of {c:?}"),
+            PositionError::OpponentInCheck => f.write_str("opponent in check"),
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+/// A builder to set up a [`Position`] piece by piece, starting from an empty board.
+#[derive(Debug, Clone)]
+pub struct PositionBuilder {
+    partial: PartialPosition,
+    hands: [[u8; PieceKind::NUM]; Color::NUM],
+    rules: Rules,
+    track_history: bool,
+    ply: u32,
+}
+
+impl PositionBuilder {
+    pub fn new() -> Self {
+        Self {
+            partial: PartialPosition::empty(),
+            hands: [[0; PieceKind::NUM]; Color::NUM],
+            rules: Rules::default(),
+            track_history: true,
+            ply: 1,
+        }
+    }
+    pub fn place(mut self, sq: Square, piece: Piece) -> Self {
+        self.partial.piece_set(sq, Some(piece));
+        self
+    }
+    /// Sets the number of `pk` in the hand of `c` to `n`.
+    pub fn hand(mut self, c: Color, pk: PieceKind, n: u8) -> Self {
+        self.hands[c.array_index()][pk.array_index()] = n;
+        self
+    }
+    pub fn side_to_move(mut self, c: Color) -> Self {
+        self.partial.side_to_move_set(c);
+        self
+    }
+    /// Sets the ply.
+    ///
+    /// Panics if `n` is 0.
+    pub fn ply(mut self, n: u32) -> Self {
+        assert!(n > 0, "ply must be positive");
+        self.ply = n;
+        self
+    }
+    pub fn rules(mut self, rules: Rules) -> Self {
+        self.rules = rules;
+        self
+    }
+    /// Sets whether the position keeps the history of the positions and moves (`true` by default).
+    ///
+    /// Without the history, [`Position::do_move`] only overwrites the current state, which saves
+    /// its memory and time in deep searches handling repetitions elsewhere. Moves can still be
+    /// undone one by one, but [`Position::repetition_count`] and [`Position::moves_since`]
+    /// return `None`, [`Position::captures_history`] is empty and [`Position::last_move`]
+    /// is `None` after [`Position::undo_move`].
+    pub fn track_history(mut self, track: bool) -> Self {
+        self.track_history = track;
+        self
+    }
+    /// Validates the setup and builds the position.
+    pub fn build(self) -> Result<Position, PositionError> {
+        let mut partial = self.partial;
+        for c in Color::all() {
+            for pk in PieceKind::all() {
+                let n = self.hands[c.array_index()][pk.array_index()];
+                if n > 0 && !Hand::is_hand_piece(pk) {
+                    return Err(PositionError::InvalidHandPiece(pk));
+                }
+                let hand = partial.hand_of_a_player_mut(c);
+                for _ in 0..n {
+                    *hand = hand.added(pk).ok_or(PositionError::TooManyPieces(pk))?;
+                }
+            }
+        }
+        for (pk, num) in Hand::all_hand_pieces().zip(HAND_PIECE_NUMS) {
+            if count_pieces(&partial, pk) > num {
+                return Err(PositionError::TooManyPieces(pk));
+            }
+        }
+        let mut pawn_files = [[false; 9]; Color::NUM];
+        for sq in Square::all() {
+            let Some(p) = partial.piece_at(sq) else {
+                continue;
+            };
+            let (pk, c) = p.to_parts();
+            // 行き所のない駒
+            if !p.has_legal_destination(sq) {
+                return Err(PositionError::StuckPiece(sq));
+            }
+            if pk == PieceKind::Pawn {
+                let file = &mut pawn_files[c.array_index()][sq.file() as usize - 1];
+                if *file {
+                    return Err(PositionError::DoublePawns(c));
+                }
+                *file = true;
+            }
+        }
+        for c in Color::all() {
+            let kings = Square::all()
+                .filter(|&sq| partial.piece_at(sq) == Some(Piece::new(PieceKind::King, c)))
+                .count();
+            if kings > 1 {
+                return Err(PositionError::TooManyPieces(PieceKind::King));
+            }
+        }
+        let mut pos = Position::with_rules(partial, self.rules);
+        pos.set_track_history(self.track_history);
+        pos.set_ply(self.ply);
+        if pos.with_side_flipped().in_check() {
+            return Err(PositionError::OpponentInCheck);
+        }
+        Ok(pos)
+    }
+}
+
+impl Default for PositionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shogi_core::Move;
+
+    #[test]
+    fn build() {
+        let pos = PositionBuilder::new()
+            .place(Square::SQ_5I, Piece::B_K)
+            .place(Square::SQ_5A, Piece::W_K)
+            .place(Square::SQ_5G, Piece::B_P)
+            .hand(Color::Black, PieceKind::Gold, 2)
+            .side_to_move(Color::White)
+            .ply(10)
+            .build()
+            .expect("failed to build");
+        assert_eq!(Some(Piece::B_P), pos.piece_at(Square::SQ_5G));
+        assert_eq!(Some(2), pos.hand(Color::Black).count(PieceKind::Gold));
+        assert_eq!(Color::White, pos.side_to_move());
+        assert_eq!(10, pos.ply());
+        // the key is computed from scratch
+        let mut partial = PartialPosition::empty();
+        partial.piece_set(Square::SQ_5I, Some(Piece::B_K));
+        partial.piece_set(Square::SQ_5A, Some(Piece::W_K));
+        partial.piece_set(Square::SQ_5G, Some(Piece::B_P));
+        let hand = partial.hand_of_a_player_mut(Color::Black);
+        *hand = hand
+            .added(PieceKind::Gold)
+            .unwrap()
+            .added(PieceKind::Gold)
+            .unwrap();
+        partial.side_to_move_set(Color::White);
+        assert_eq!(Position::new(partial).key(), pos.key());
+    }
+
+    #[test]
+    fn track_history() {
+        let builder = PositionBuilder::new()
+            .place(Square::SQ_5I, Piece::B_K)
+            .place(Square::SQ_5A, Piece::W_K)
+            .place(Square::SQ_5E, Piece::B_R)
+            .place(Square::SQ_5C, Piece::W_P);
+        let capture = Move::Normal {
+            from: Square::SQ_5E,
+            to: Square::SQ_5C,
+            promote: true,
+        };
+        let mut pos = builder.clone().build().expect("failed to build");
+        pos.do_move(capture);
+        assert_eq!(&[Piece::W_P], pos.captures_history());
+        assert_eq!(Some(&[capture][..]), pos.moves_since(1));
+        assert_eq!(Some(1), pos.repetition_count());
+
+        let mut pos = builder
+            .track_history(false)
+            .build()
+            .expect("failed to build");
+        let key = pos.key();
+        let checkers = pos.checkers();
+        pos.do_move(capture);
+        assert_eq!(2, pos.ply());
+        assert_eq!(Some(capture), pos.last_move());
+        assert!(pos.captures_history().is_empty());
+        assert_eq!(None, pos.moves_since(1));
+        assert_eq!(None, pos.repetition_count());
+        // 戻すと取った駒と状態が再計算される
+        pos.undo_move(capture);
+        assert_eq!(1, pos.ply());
+        assert_eq!(key, pos.key());
+        assert_eq!(checkers, pos.checkers());
+        assert_eq!(Some(Piece::W_P), pos.piece_at(Square::SQ_5C));
+        assert_eq!(Some(Piece::B_R), pos.piece_at(Square::SQ_5E));
+        assert_eq!(None, pos.last_move());
+
+        // 千日手は判定しない
+        let moves = [
+            (Square::SQ_5I, Square::SQ_4I),
+            (Square::SQ_5A, Square::SQ_4A),
+            (Square::SQ_4I, Square::SQ_5I),
+            (Square::SQ_4A, Square::SQ_5A),
+        ];
+        for i in 0..12 {
+            let (from, to) = moves[i % 4];
+            pos.do_move(Move::Normal {
+                from,
+                to,
+                promote: false,
+            });
+            assert!(!pos.can_claim_repetition_draw());
+        }
+    }
+
+    #[test]
+    fn build_errors() {
+        let b = PositionBuilder::new()
+            .place(Square::SQ_5I, Piece::B_K)
+            .place(Square::SQ_5A, Piece::W_K);
+        assert_eq!(
+            Some(PositionError::InvalidHandPiece(PieceKind::King)),
+            b.clone()
+                .hand(Color::Black, PieceKind::King, 1)
+                .build()
+                .err()
+        );
+        assert_eq!(
+            Some(PositionError::TooManyPieces(PieceKind::Rook)),
+            b.clone()
+                .place(Square::SQ_2H, Piece::B_PR)
+                .hand(Color::White, PieceKind::Rook, 2)
+                .build()
+                .err()
+        );
+        assert_eq!(
+            Some(PositionError::TooManyPieces(PieceKind::King)),
+            b.clone().place(Square::SQ_1I, Piece::B_K).build().err()
+        );
+        assert_eq!(
+            Some(PositionError::StuckPiece(Square::SQ_1B)),
+            b.clone().place(Square::SQ_1B, Piece::B_N).build().err()
+        );
+        assert_eq!(
+            Some(PositionError::StuckPiece(Square::SQ_1I)),
+            b.clone().place(Square::SQ_1I, Piece::W_L).build().err()
+        );
+        assert_eq!(
+            Some(PositionError::DoublePawns(Color::White)),
+            b.clone()
+                .place(Square::SQ_3C, Piece::W_P)
+                .place(Square::SQ_3G, Piece::W_P)
+                .build()
+                .err()
+        );
+        assert_eq!(
+            Some(PositionError::OpponentInCheck),
+            b.clone().place(Square::SQ_5B, Piece::B_G).build().err()
+        );
+        assert!(b
+            .place(Square::SQ_5B, Piece::B_G)
+            .side_to_move(Color::White)
+            .build()
+            .is_ok());
+    }
+}
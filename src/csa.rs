@@ -0,0 +1,397 @@
+use crate::hand::{count_pieces, HAND_PIECE_NUMS};
+use crate::Position;
+use shogi_core::{Color, Hand, Move, PartialPosition, Piece, PieceKind, Square};
+use std::fmt;
+
+/// An error that can occur while parsing CSA records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsaError {
+    /// The statement at `line` (1-origin) is malformed.
+    InvalidLine(usize),
+    /// The record has no complete position block.
+    MissingPosition,
+    /// The move at `ply` is illegal in the position reached so far.
+    IllegalMove(u32),
+}
+
+impl fmt::Display for CsaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsaError::InvalidLine(line) => write!(f, "invalid statement at line {line}"),
+            CsaError::MissingPosition => f.write_str("missing position"),
+            CsaError::IllegalMove(ply) => write!(f, "illegal move at ply {ply}"),
+        }
+    }
+}
+
+impl std::error::Error for CsaError {}
+
+#[rustfmt::skip]
+const PIECE_KINDS: [(&str, PieceKind); PieceKind::NUM] = [
+    ("FU", PieceKind::Pawn),    ("KY", PieceKind::Lance),     ("KE", PieceKind::Knight),
+    ("GI", PieceKind::Silver),  ("KI", PieceKind::Gold),      ("KA", PieceKind::Bishop),
+    ("HI", PieceKind::Rook),    ("OU", PieceKind::King),      ("TO", PieceKind::ProPawn),
+    ("NY", PieceKind::ProLance), ("NK", PieceKind::ProKnight), ("NG", PieceKind::ProSilver),
+    ("UM", PieceKind::ProBishop), ("RY", PieceKind::ProRook),
+];
+
+pub(crate) fn parse_piece_kind(s: &str) -> Option<PieceKind> {
+    PIECE_KINDS
+        .iter()
+        .find(|&&(name, _)| name == s)
+        .map(|&(_, pk)| pk)
+}
+
+pub(crate) fn parse_color(s: &str) -> Option<Color> {
+    match s {
+        "+" => Some(Color::Black),
+        "-" => Some(Color::White),
+        _ => None,
+    }
+}
+
+/// Parses a board row such as `-KY-KE-GI-KI-OU-KI-GI-KE-KY` (without the `Pn` prefix).
+pub(crate) fn parse_row(s: &str) -> Option<[Option<Piece>; 9]> {
+    let s = format!("{s:<27}");
+    if s.len() != 27 || !s.is_ascii() {
+        return None;
+    }
+    let mut row = [None; 9];
+    for (i, p) in row.iter_mut().enumerate() {
+        let cell = &s[i * 3..i * 3 + 3];
+        if cell != " * " {
+            let c = parse_color(&cell[..1])?;
+            *p = Some(Piece::new(parse_piece_kind(&cell[1..])?, c));
+        }
+    }
+    Some(row)
+}
+
+fn parse_square(s: &str) -> Option<Square> {
+    let mut chars = s.chars();
+    let file = chars.next()?.to_digit(10)?;
+    let rank = chars.next()?.to_digit(10)?;
+    Square::new(file as u8, rank as u8)
+}
+
+/// Parses a CSA game record into the starting position and the sequence of moves.
+///
+/// Comments (`'`), metadata (`V`, `N`, `$`) and time (`T`) statements are skipped,
+/// and special moves (`%`) end the record. Every move is validated against the position.
+pub fn parse_game(text: &str) -> Result<(Position, Vec<Move>), CsaError> {
+    let mut partial = PartialPosition::empty();
+    let mut pos = None;
+    let mut moves = Vec::new();
+    'lines: for (i, line) in text.lines().enumerate() {
+        let err = CsaError::InvalidLine(i + 1);
+        for stmt in line.trim_end().split(',') {
+            if stmt.is_empty() || stmt.starts_with(['\'', 'V', 'N', '$', 'T']) {
+                continue;
+            }
+            if stmt.starts_with('%') {
+                break 'lines;
+            }
+            match pos.as_mut() {
+                None => {
+                    if let Some(c) = parse_color(stmt) {
+                        partial.side_to_move_set(c);
+                        pos = Some(Position::new(partial.clone()));
+                    } else {
+                        parse_position_statement(&mut partial, stmt).ok_or(err)?;
+                    }
+                }
+                Some(pos) => {
+                    let (c, from, to, pk) = parse_move_statement(stmt).ok_or(err)?;
+                    let m = match from {
+                        Some(from) => Move::Normal {
+                            from,
+                            to,
+                            promote: pos.piece_at(from).is_some_and(|p| p.piece_kind() != pk),
+                        },
+                        None => Move::Drop {
+                            to,
+                            piece: Piece::new(pk, c),
+                        },
+                    };
+                    if c != pos.side_to_move() || !pos.legal_moves().contains(&m) {
+                        return Err(CsaError::IllegalMove(pos.ply()));
+                    }
+                    pos.do_move(m);
+                    moves.push(m);
+                }
+            }
+        }
+    }
+    let mut pos = pos.ok_or(CsaError::MissingPosition)?;
+    // rewind to the starting position
+    for &m in moves.iter().rev() {
+        pos.undo_move(m);
+    }
+    Ok((pos, moves))
+}
+
+impl Position {
+    /// Creates a position from a board diagram of nine CSA rows such as
+    /// `P1-KY-KE-GI-KI-OU-KI-GI-KE-KY`, as written in the comments of the tests.
+    ///
+    /// Leading and trailing whitespace and empty lines are ignored. The rows must be `P1` to `P9` in order.
+    pub fn from_grid(
+        grid: &str,
+        black_hand: Hand,
+        white_hand: Hand,
+        side_to_move: Color,
+    ) -> Result<Position, CsaError> {
+        let mut partial = PartialPosition::empty();
+        let mut rank = 0;
+        for (i, line) in grid.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = CsaError::InvalidLine(i + 1);
+            rank += 1;
+            let row = line
+                .strip_prefix(&format!("P{rank}"))
+                .and_then(parse_row)
+                .ok_or(err)?;
+            for (file, p) in (1..=9).rev().zip(row) {
+                partial.piece_set(Square::new(file, rank).ok_or(err)?, p);
+            }
+        }
+        if rank != 9 {
+            return Err(CsaError::MissingPosition);
+        }
+        *partial.hand_of_a_player_mut(Color::Black) = black_hand;
+        *partial.hand_of_a_player_mut(Color::White) = white_hand;
+        partial.side_to_move_set(side_to_move);
+        Ok(Position::new(partial))
+    }
+}
+
+fn parse_move_statement(stmt: &str) -> Option<(Color, Option<Square>, Square, PieceKind)> {
+    if stmt.len() != 7 || !stmt.is_ascii() {
+        return None;
+    }
+    let c = parse_color(&stmt[..1])?;
+    let from = if &stmt[1..3] == "00" {
+        None
+    } else {
+        Some(parse_square(&stmt[1..3])?)
+    };
+    let to = parse_square(&stmt[3..5])?;
+    let pk = parse_piece_kind(&stmt[5..7])?;
+    Some((c, from, to, pk))
+}
+
+fn parse_position_statement(partial: &mut PartialPosition, stmt: &str) -> Option<()> {
+    if !stmt.is_ascii() || !stmt.starts_with('P') || stmt.len() < 2 {
+        return None;
+    }
+    match &stmt[1..2] {
+        "I" => {
+            *partial = PartialPosition::startpos();
+            for chunk in stmt.as_bytes()[2..].chunks(4) {
+                let chunk = std::str::from_utf8(chunk).ok()?;
+                let sq = parse_square(chunk.get(..2)?)?;
+                let pk = parse_piece_kind(chunk.get(2..)?)?;
+                if partial.piece_at(sq)?.piece_kind() != pk {
+                    return None;
+                }
+                partial.piece_set(sq, None);
+            }
+        }
+        "+" | "-" => {
+            let c = parse_color(&stmt[1..2])?;
+            for chunk in stmt.as_bytes()[2..].chunks(4) {
+                let chunk = std::str::from_utf8(chunk).ok()?;
+                match (chunk.get(..2)?, chunk.get(2..)?) {
+                    ("00", "AL") => {
+                        for (pk, num) in Hand::all_hand_pieces().zip(HAND_PIECE_NUMS) {
+                            let rest = num.checked_sub(count_pieces(partial, pk))?;
+                            let hand = partial.hand_of_a_player_mut(c);
+                            for _ in 0..rest {
+                                *hand = hand.added(pk)?;
+                            }
+                        }
+                    }
+                    ("00", s) => {
+                        let hand = partial.hand_of_a_player_mut(c);
+                        *hand = hand.added(parse_piece_kind(s)?)?;
+                    }
+                    (sq, s) => {
+                        partial.piece_set(
+                            parse_square(sq)?,
+                            Some(Piece::new(parse_piece_kind(s)?, c)),
+                        );
+                    }
+                }
+            }
+        }
+        rank => {
+            let rank = rank.parse::<u8>().ok()?;
+            let row = parse_row(&stmt[2..])?;
+            for (i, p) in row.into_iter().enumerate() {
+                partial.piece_set(Square::new(9 - i as u8, rank)?, p);
+            }
+        }
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_game_hirate() {
+        let text = "\
+V2.2
+N+sente
+N-gote
+$EVENT:test
+PI
++
++7776FU,T1
+-3334FU
+'comment
+T2
++8822UM
+-3122GI
++0045KA
+%TORYO
+";
+        let (pos, moves) = parse_game(text).expect("failed to parse");
+        assert_eq!(Position::default().key(), pos.key());
+        assert_eq!(
+            vec![
+                Move::Normal {
+                    from: Square::SQ_7G,
+                    to: Square::SQ_7F,
+                    promote: false,
+                },
+                Move::Normal {
+                    from: Square::SQ_3C,
+                    to: Square::SQ_3D,
+                    promote: false,
+                },
+                Move::Normal {
+                    from: Square::SQ_8H,
+                    to: Square::SQ_2B,
+                    promote: true,
+                },
+                Move::Normal {
+                    from: Square::SQ_3A,
+                    to: Square::SQ_2B,
+                    promote: false,
+                },
+                Move::Drop {
+                    to: Square::SQ_4E,
+                    piece: Piece::B_B,
+                },
+            ],
+            moves
+        );
+    }
+
+    #[test]
+    fn parse_game_position_block() {
+        // the position of `is_pawn_drop_mate` test
+        let text = "\
+P1 *  *  *  *  *  *  *  *  *
+P2 *  *  *  *  *  *  * -FU-FU
+P3 *  *  *  *  *  *  *  * -OU
+P4 *  *  *  *  *  *  * +FU *
+P5 *  *  *  *  *  *  * +KI *
+P6 *  *  *  *  *  *  *  *  *
+P7 *  *  *  *  *  *  *  *  *
+P8 *  *  *  *  *  *  *  *  *
+P9 *  *  *  *  *  *  *  *  *
+P+00FU
+P-00AL
++
++2515KI
+";
+        let (pos, moves) = parse_game(text).expect("failed to parse");
+        assert_eq!(Some(Piece::W_K), pos.piece_at(Square::SQ_1C));
+        assert_eq!(Some(1), pos.hand(Color::Black).count(PieceKind::Pawn));
+        assert_eq!(Some(14), pos.hand(Color::White).count(PieceKind::Pawn));
+        assert_eq!(Some(3), pos.hand(Color::White).count(PieceKind::Gold));
+        assert_eq!(1, moves.len());
+    }
+
+    #[test]
+    fn parse_game_errors() {
+        assert_eq!(Some(CsaError::MissingPosition), parse_game("V2.2\n").err());
+        assert_eq!(
+            Some(CsaError::IllegalMove(3)),
+            parse_game("PI\n+\n+7776FU\n-3334FU\n+7775FU\n").err()
+        );
+        assert_eq!(
+            Some(CsaError::IllegalMove(2)),
+            parse_game("PI\n+\n+7776FU\n+2726FU\n").err()
+        );
+        assert_eq!(
+            Some(CsaError::InvalidLine(3)),
+            parse_game("PI\n+\n+7776XX\n").err()
+        );
+        assert_eq!(Some(CsaError::InvalidLine(1)), parse_game("P0\n+\n").err());
+    }
+
+    #[test]
+    fn from_grid() {
+        // +7776FU,-3334FU,+2726FU
+        let grid = "
+            P1-KY-KE-GI-KI-OU-KI-GI-KE-KY
+            P2 * -HI *  *  *  *  * -KA *
+            P3-FU-FU-FU-FU-FU-FU * -FU-FU
+            P4 *  *  *  *  *  * -FU *  *
+            P5 *  *  *  *  *  *  *  *  *
+            P6 *  * +FU *  *  *  * +FU *
+            P7+FU+FU * +FU+FU+FU+FU * +FU
+            P8 * +KA *  *  *  *  * +HI *
+            P9+KY+KE+GI+KI+OU+KI+GI+KE+KY
+        ";
+        let pos = Position::from_grid(grid, Hand::new(), Hand::new(), Color::White)
+            .expect("failed to parse");
+        let mut joined = Position::default();
+        for (from, to) in [
+            (Square::SQ_7G, Square::SQ_7F),
+            (Square::SQ_3C, Square::SQ_3D),
+            (Square::SQ_2G, Square::SQ_2F),
+        ] {
+            joined.do_move(Move::Normal {
+                from,
+                to,
+                promote: false,
+            });
+        }
+        assert_eq!(joined.keys(), pos.keys());
+
+        let hand = Hand::new().added(PieceKind::Pawn).unwrap();
+        let pos =
+            Position::from_grid(grid, hand, Hand::new(), Color::White).expect("failed to parse");
+        assert_ne!(joined.keys(), pos.keys());
+
+        let lines = grid.lines().collect::<Vec<_>>();
+        assert_eq!(
+            Some(CsaError::MissingPosition),
+            Position::from_grid(
+                &lines[..9].join("\n"),
+                Hand::new(),
+                Hand::new(),
+                Color::Black
+            )
+            .err()
+        );
+        assert_eq!(
+            Some(CsaError::InvalidLine(3)),
+            Position::from_grid(
+                &[&lines[..2], &lines[3..]].concat().join("\n"),
+                Hand::new(),
+                Hand::new(),
+                Color::Black
+            )
+            .err()
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use crate::Position;
+use shogi_core::{Color, Hand, Piece, PieceKind, Square};
+
+/// Differences between two positions, returned by [`Position::diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PositionDiff {
+    /// Squares whose pieces differ, with the pieces before and after, in the order of [`Square::all`].
+    pub squares: Vec<(Square, Option<Piece>, Option<Piece>)>,
+    /// `true` if the side to move differs.
+    pub side_changed: bool,
+    hands: [[i8; PieceKind::NUM]; Color::NUM],
+}
+
+impl PositionDiff {
+    /// Returns the change of the number of `pk` in the hand of `c`.
+    pub fn hand_delta(&self, c: Color, pk: PieceKind) -> i8 {
+        self.hands[c.array_index()][pk.array_index()]
+    }
+    pub fn is_empty(&self) -> bool {
+        self.squares.is_empty()
+            && !self.side_changed
+            && self.hands.iter().flatten().all(|&delta| delta == 0)
+    }
+}
+
+impl Position {
+    /// Returns the differences of the board, hands and side to move from `self` to `other`.
+    ///
+    /// Given two consecutive positions, the move played can be inferred from the result.
+    pub fn diff(&self, other: &Position) -> PositionDiff {
+        let squares = Square::all()
+            .filter_map(|sq| {
+                let (before, after) = (self.piece_at(sq), other.piece_at(sq));
+                (before != after).then_some((sq, before, after))
+            })
+            .collect();
+        let mut hands = [[0; PieceKind::NUM]; Color::NUM];
+        for c in Color::all() {
+            for pk in Hand::all_hand_pieces() {
+                let before = self.hand(c).count(pk).unwrap_or(0);
+                let after = other.hand(c).count(pk).unwrap_or(0);
+                hands[c.array_index()][pk.array_index()] = after as i8 - before as i8;
+            }
+        }
+        PositionDiff {
+            squares,
+            side_changed: self.side_to_move() != other.side_to_move(),
+            hands,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shogi_core::Move;
+
+    #[test]
+    fn diff() {
+        let mut pos = Position::default();
+        assert!(pos.diff(&pos).is_empty());
+        // +7776FU,-3334FU
+        for m in [
+            Move::Normal {
+                from: Square::SQ_7G,
+                to: Square::SQ_7F,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_3C,
+                to: Square::SQ_3D,
+                promote: false,
+            },
+        ] {
+            pos.do_move(m);
+        }
+        let before = pos.clone();
+        // +8822UM
+        pos.do_move(Move::Normal {
+            from: Square::SQ_8H,
+            to: Square::SQ_2B,
+            promote: true,
+        });
+        let diff = before.diff(&pos);
+        assert_eq!(
+            vec![
+                (Square::SQ_2B, Some(Piece::W_B), Some(Piece::B_PB)),
+                (Square::SQ_8H, Some(Piece::B_B), None),
+            ],
+            diff.squares
+        );
+        assert!(diff.side_changed);
+        assert_eq!(1, diff.hand_delta(Color::Black, PieceKind::Bishop));
+        for c in Color::all() {
+            for pk in Hand::all_hand_pieces() {
+                if (c, pk) != (Color::Black, PieceKind::Bishop) {
+                    assert_eq!(0, diff.hand_delta(c, pk));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use crate::sfen::SfenError;
+use shogi_core::{Color, Hand, PartialPosition, PieceKind, Square};
+use std::fmt;
+
+/// Maximum number of pieces of a kind in a hand.
+const MAX_NUM: u8 = 18;
+
+/// Numbers of each hand piece kind in a full set, in the order of [`Hand::all_hand_pieces`].
+pub(crate) const HAND_PIECE_NUMS: [u8; Hand::NUM_HAND_PIECES] = [18, 4, 4, 4, 4, 2, 2];
+
+/// Hand piece kinds in the conventional SFEN order.
+const SFEN_ORDER: [(PieceKind, char); Hand::NUM_HAND_PIECES] = [
+    (PieceKind::Rook, 'R'),
+    (PieceKind::Bishop, 'B'),
+    (PieceKind::Gold, 'G'),
+    (PieceKind::Silver, 'S'),
+    (PieceKind::Knight, 'N'),
+    (PieceKind::Lance, 'L'),
+    (PieceKind::Pawn, 'P'),
+];
+
+/// An error returned when a hand would hold more than 18 pieces of a kind,
+/// or a piece kind which can't be in hand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandOverflow;
+
+impl fmt::Display for HandOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("hand overflow")
+    }
+}
+
+impl std::error::Error for HandOverflow {}
+
+/// An error returned when removing pieces which a hand doesn't hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandUnderflow;
+
+impl fmt::Display for HandUnderflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("hand underflow")
+    }
+}
+
+impl std::error::Error for HandUnderflow {}
+
+/// Extension methods for [`Hand`].
+pub trait HandExt: Sized {
+    /// Parses the hand of a single player written as in SFEN (e.g. `"2G5P"`, or `"-"` if empty).
+    ///
+    /// Both uppercase and lowercase letters are accepted, but they can't be mixed.
+    fn from_sfen(s: &str) -> Result<Self, SfenError>;
+    /// Formats the hand as in SFEN with uppercase letters, in the order of R, B, G, S, N, L, P.
+    fn to_sfen(&self) -> String;
+    /// Adds `n` pieces of `pk`. The hand is left unchanged on error.
+    fn add(&mut self, pk: PieceKind, n: u8) -> Result<(), HandOverflow>;
+    /// Removes `n` pieces of `pk`. The hand is left unchanged on error.
+    fn remove(&mut self, pk: PieceKind, n: u8) -> Result<(), HandUnderflow>;
+    /// Returns the held piece kinds and their numbers in the conventional SFEN order:
+    /// R, B, G, S, N, L, P. Piece kinds not held are skipped.
+    ///
+    /// Note that [`Hand::all_hand_pieces`], used for the zobrist keys, yields the reverse order.
+    fn pieces(self) -> impl Iterator<Item = (PieceKind, u8)>;
+}
+
+impl HandExt for Hand {
+    fn from_sfen(s: &str) -> Result<Self, SfenError> {
+        if s == "-" {
+            return Ok(Hand::default());
+        }
+        let upper = s.chars().any(|c| c.is_ascii_uppercase());
+        let lower = s.chars().any(|c| c.is_ascii_lowercase());
+        if s.is_empty() || upper == lower {
+            return Err(SfenError::InvalidHand);
+        }
+        let mut hand = Hand::default();
+        let mut num = None;
+        for c in s.chars() {
+            if let Some(d) = c.to_digit(10) {
+                num = Some(num.unwrap_or(0) * 10 + d);
+                if num > Some(MAX_NUM as u32) {
+                    return Err(SfenError::InvalidHand);
+                }
+                continue;
+            }
+            let pk = SFEN_ORDER
+                .iter()
+                .find(|&&(_, ch)| ch == c.to_ascii_uppercase())
+                .map(|&(pk, _)| pk)
+                .ok_or(SfenError::InvalidHand)?;
+            match num.take() {
+                Some(0) => return Err(SfenError::InvalidHand),
+                n => {
+                    for _ in 0..n.unwrap_or(1) {
+                        hand = hand.added(pk).ok_or(SfenError::InvalidHand)?;
+                    }
+                }
+            }
+        }
+        if num.is_some() {
+            return Err(SfenError::InvalidHand);
+        }
+        Ok(hand)
+    }
+    fn to_sfen(&self) -> String {
+        let mut s = String::new();
+        for (pk, c) in SFEN_ORDER {
+            match self.count(pk).unwrap_or_default() {
+                0 => {}
+                1 => s.push(c),
+                n => {
+                    s.push_str(&n.to_string());
+                    s.push(c);
+                }
+            }
+        }
+        if s.is_empty() {
+            s.push('-');
+        }
+        s
+    }
+    fn add(&mut self, pk: PieceKind, n: u8) -> Result<(), HandOverflow> {
+        let count = self.count(pk).ok_or(HandOverflow)?;
+        if count.checked_add(n).is_none_or(|sum| sum > MAX_NUM) {
+            return Err(HandOverflow);
+        }
+        for _ in 0..n {
+            *self = self.added(pk).ok_or(HandOverflow)?;
+        }
+        Ok(())
+    }
+    fn remove(&mut self, pk: PieceKind, n: u8) -> Result<(), HandUnderflow> {
+        if self.count(pk).unwrap_or_default() < n {
+            return Err(HandUnderflow);
+        }
+        for _ in 0..n {
+            *self = self.removed(pk).ok_or(HandUnderflow)?;
+        }
+        Ok(())
+    }
+    fn pieces(self) -> impl Iterator<Item = (PieceKind, u8)> {
+        SFEN_ORDER
+            .into_iter()
+            .filter_map(move |(pk, _)| self.count(pk).filter(|&n| n > 0).map(|n| (pk, n)))
+    }
+}
+
+/// Counts the pieces of `pk` (including promoted ones) on the board and in both hands.
+pub(crate) fn count_pieces(partial: &PartialPosition, pk: PieceKind) -> u8 {
+    let on_board = Square::all()
+        .filter_map(|sq| partial.piece_at(sq))
+        .filter(|p| p.piece_kind().unpromote().unwrap_or(p.piece_kind()) == pk)
+        .count() as u8;
+    let in_hand = Color::all()
+        .iter()
+        .filter_map(|&c| partial.hand_of_a_player(c).count(pk))
+        .sum::<u8>();
+    on_board + in_hand
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sfen() {
+        let hand = Hand::from_sfen("2G5P").expect("failed to parse");
+        assert_eq!(Some(2), hand.count(PieceKind::Gold));
+        assert_eq!(Some(5), hand.count(PieceKind::Pawn));
+        assert_eq!("2G5P", hand.to_sfen());
+        assert_eq!(hand, Hand::from_sfen("5p2g").expect("failed to parse"));
+
+        let hand = Hand::from_sfen("RB2G3S4N2L18P").expect("failed to parse");
+        assert_eq!("RB2G3S4N2L18P", hand.to_sfen());
+        assert_eq!(
+            Hand::default(),
+            Hand::from_sfen("-").expect("failed to parse")
+        );
+        assert_eq!("-", Hand::default().to_sfen());
+    }
+
+    #[test]
+    fn invalid_sfen() {
+        for s in ["", "2", "G2", "0P", "19P", "K", "2Gp", "X"] {
+            assert_eq!(Err(SfenError::InvalidHand), Hand::from_sfen(s), "{s}");
+        }
+    }
+
+    #[test]
+    fn add_remove() {
+        let mut hand = Hand::default();
+        assert_eq!(Ok(()), hand.add(PieceKind::Pawn, 17));
+        assert_eq!(Ok(()), hand.add(PieceKind::Pawn, 1));
+        assert_eq!(Some(18), hand.count(PieceKind::Pawn));
+        assert_eq!(Err(HandOverflow), hand.add(PieceKind::Pawn, 1));
+        assert_eq!(Err(HandOverflow), hand.add(PieceKind::Pawn, 255));
+        assert_eq!(Err(HandOverflow), hand.add(PieceKind::King, 1));
+        assert_eq!(Err(HandOverflow), hand.add(PieceKind::ProPawn, 1));
+        assert_eq!(Some(18), hand.count(PieceKind::Pawn));
+
+        assert_eq!(Ok(()), hand.remove(PieceKind::Pawn, 16));
+        assert_eq!(Some(2), hand.count(PieceKind::Pawn));
+        assert_eq!(Err(HandUnderflow), hand.remove(PieceKind::Pawn, 3));
+        assert_eq!(Err(HandUnderflow), hand.remove(PieceKind::Gold, 1));
+        assert_eq!(Ok(()), hand.remove(PieceKind::King, 0));
+        assert_eq!(Some(2), hand.count(PieceKind::Pawn));
+        assert_eq!(Ok(()), hand.remove(PieceKind::Pawn, 2));
+        assert_eq!(Hand::default(), hand);
+    }
+
+    #[test]
+    fn pieces() {
+        let hand = Hand::from_sfen("3PL2NSGBR").expect("failed to parse");
+        assert_eq!(
+            vec![
+                (PieceKind::Rook, 1),
+                (PieceKind::Bishop, 1),
+                (PieceKind::Gold, 1),
+                (PieceKind::Silver, 1),
+                (PieceKind::Knight, 2),
+                (PieceKind::Lance, 1),
+                (PieceKind::Pawn, 3),
+            ],
+            hand.pieces().collect::<Vec<_>>()
+        );
+        let hand = Hand::from_sfen("2p3nb").expect("failed to parse");
+        assert_eq!(
+            vec![
+                (PieceKind::Bishop, 1),
+                (PieceKind::Knight, 3),
+                (PieceKind::Pawn, 2),
+            ],
+            hand.pieces().collect::<Vec<_>>()
+        );
+        assert_eq!(None, Hand::default().pieces().next());
+    }
+}
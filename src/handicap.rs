@@ -0,0 +1,187 @@
+use crate::Position;
+use shogi_core::{Color, Square};
+
+/// Standard handicap (駒落ち) setups.
+///
+/// The handicap giver (上手) plays White, loses the listed pieces and moves first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Handicap {
+    /// 香落ち: the left lance of the handicap giver (1a).
+    Lance,
+    /// 右香落ち: the right lance of the handicap giver (9a).
+    RightLance,
+    /// 角落ち
+    Bishop,
+    /// 飛車落ち
+    Rook,
+    /// 飛香落ち: the rook and the left lance (1a).
+    RookLance,
+    /// 二枚落ち: the rook and the bishop.
+    TwoPieces,
+    /// 四枚落ち: the rook, the bishop and both lances.
+    FourPieces,
+    /// 六枚落ち: [`Handicap::FourPieces`] and both knights.
+    SixPieces,
+    /// 八枚落ち: [`Handicap::SixPieces`] and both silvers.
+    EightPieces,
+    /// 十枚落ち: [`Handicap::EightPieces`] and both golds.
+    TenPieces,
+}
+
+impl Handicap {
+    /// Returns the squares of the removed pieces of White.
+    ///
+    /// "Left" and "right" are from the handicap giver's point of view,
+    /// so the left lance stands on 1a and the right lance on 9a.
+    pub fn removed_squares(self) -> &'static [Square] {
+        const TEN: [Square; 10] = [
+            Square::SQ_8B,
+            Square::SQ_2B,
+            Square::SQ_1A,
+            Square::SQ_9A,
+            Square::SQ_2A,
+            Square::SQ_8A,
+            Square::SQ_3A,
+            Square::SQ_7A,
+            Square::SQ_4A,
+            Square::SQ_6A,
+        ];
+        match self {
+            Handicap::Lance => &[Square::SQ_1A],
+            Handicap::RightLance => &[Square::SQ_9A],
+            Handicap::Bishop => &[Square::SQ_2B],
+            Handicap::Rook => &[Square::SQ_8B],
+            Handicap::RookLance => &[Square::SQ_8B, Square::SQ_1A],
+            Handicap::TwoPieces => &TEN[..2],
+            Handicap::FourPieces => &TEN[..4],
+            Handicap::SixPieces => &TEN[..6],
+            Handicap::EightPieces => &TEN[..8],
+            Handicap::TenPieces => &TEN[..],
+        }
+    }
+}
+
+impl Position {
+    /// Returns the starting position of `handicap`, with White to move.
+    pub fn handicap(handicap: Handicap) -> Position {
+        let mut partial = shogi_core::PartialPosition::startpos();
+        for &sq in handicap.removed_squares() {
+            partial.piece_set(sq, None);
+        }
+        partial.side_to_move_set(Color::White);
+        Position::new(partial)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shogi_core::{Move, Piece, PieceKind};
+
+    #[test]
+    fn removed_squares() {
+        // 香落ちは上手から見て左 (1一)、右香落ちは右 (9一) の香を落とす
+        assert_eq!(&[Square::SQ_1A], Handicap::Lance.removed_squares());
+        assert_eq!(&[Square::SQ_9A], Handicap::RightLance.removed_squares());
+        assert_eq!(
+            &[Square::SQ_8B, Square::SQ_1A],
+            Handicap::RookLance.removed_squares()
+        );
+        let pos = Position::handicap(Handicap::RightLance);
+        assert_eq!(Some(Piece::W_L), pos.piece_at(Square::SQ_1A));
+        assert_eq!(None, pos.piece_at(Square::SQ_9A));
+
+        // 落とす駒の種類と、左右対称に落とされること
+        let startpos = Position::default();
+        for (handicap, kinds) in [
+            (Handicap::Lance, &[PieceKind::Lance][..]),
+            (Handicap::RightLance, &[PieceKind::Lance]),
+            (Handicap::Bishop, &[PieceKind::Bishop]),
+            (Handicap::Rook, &[PieceKind::Rook]),
+            (Handicap::RookLance, &[PieceKind::Rook, PieceKind::Lance]),
+            (Handicap::TwoPieces, &[PieceKind::Rook, PieceKind::Bishop]),
+            (
+                Handicap::EightPieces,
+                &[
+                    PieceKind::Rook,
+                    PieceKind::Bishop,
+                    PieceKind::Lance,
+                    PieceKind::Lance,
+                    PieceKind::Knight,
+                    PieceKind::Knight,
+                    PieceKind::Silver,
+                    PieceKind::Silver,
+                ],
+            ),
+        ] {
+            let removed = handicap
+                .removed_squares()
+                .iter()
+                .map(|&sq| startpos.piece_at(sq).map(|p| (p.color(), p.piece_kind())))
+                .collect::<Vec<_>>();
+            let expected = kinds
+                .iter()
+                .map(|&pk| Some((Color::White, pk)))
+                .collect::<Vec<_>>();
+            assert_eq!(expected, removed, "{handicap:?}");
+        }
+        for handicap in [
+            Handicap::FourPieces,
+            Handicap::SixPieces,
+            Handicap::EightPieces,
+            Handicap::TenPieces,
+        ] {
+            let squares = &handicap.removed_squares()[2..];
+            for sq in squares {
+                let mirrored = Square::new(10 - sq.file(), sq.rank()).unwrap();
+                assert!(squares.contains(&mirrored), "{handicap:?} {sq:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn handicap() {
+        let pos = Position::handicap(Handicap::Lance);
+        assert_eq!(None, pos.piece_at(Square::SQ_1A));
+        assert_eq!(Some(Piece::W_L), pos.piece_at(Square::SQ_9A));
+        assert_eq!(Some(Piece::B_L), pos.piece_at(Square::SQ_1I));
+        assert_eq!(Color::White, pos.side_to_move());
+        assert_eq!(1, pos.ply());
+        let expected =
+            Position::from_sfen("lnsgkgsn1/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1")
+                .expect("failed to parse");
+        assert_eq!(expected.keys(), pos.keys());
+
+        // 上手が指した後も key が一致する
+        let mut pos = Position::handicap(Handicap::Lance);
+        let m = Move::Normal {
+            from: Square::SQ_3C,
+            to: Square::SQ_3D,
+            promote: false,
+        };
+        pos.do_move(m);
+        let expected = Position::from_sfen(
+            "lnsgkgsn1/1r5b1/pppppp1pp/6p2/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 2",
+        )
+        .expect("failed to parse");
+        assert_eq!(expected.keys(), pos.keys());
+
+        let sfens = [
+            (
+                Handicap::TwoPieces,
+                "lnsgkgsnl/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            ),
+            (
+                Handicap::SixPieces,
+                "2sgkgs2/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            ),
+            (
+                Handicap::TenPieces,
+                "4k4/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            ),
+        ];
+        for (handicap, sfen) in sfens {
+            assert_eq!(sfen, Position::handicap(handicap).to_sfen());
+        }
+    }
+}
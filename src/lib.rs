@@ -1,7 +1,36 @@
+pub mod attacks;
 mod bitboard;
+mod builder;
+pub mod csa;
+mod diff;
+mod hand;
+mod handicap;
+pub mod mate;
 mod movegen;
+mod mv;
+mod perft;
+mod piece;
 mod position;
+mod rules;
+mod score;
+mod see;
+pub mod sfen;
+mod square;
 mod tables;
+mod values;
 mod zobrist;
 
-pub use position::Position;
+pub use bitboard::Bitboard;
+pub use builder::{PositionBuilder, PositionError};
+pub use diff::PositionDiff;
+pub use hand::{HandExt, HandOverflow, HandUnderflow};
+pub use handicap::Handicap;
+pub use movegen::{DropRejection, GenStats, MoveList, MAX_LEGAL_MOVES};
+pub use mv::{MoveExt, PartialMove, PlayError};
+pub use perft::{perft, perft_tt, PerftTable};
+pub use piece::PieceExt;
+pub use position::{Position, UndoToken};
+pub use rules::Rules;
+pub use score::Score;
+pub use square::{square, SquareExt};
+pub use values::PieceValues;
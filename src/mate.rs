@@ -0,0 +1,168 @@
+use crate::Position;
+use shogi_core::Move;
+
+impl Position {
+    /// Searches for a forced mate (詰将棋) of the side to move within `max_ply` plies.
+    ///
+    /// Every move of the attacker must give check, and the defender may play any legal move.
+    /// The shortest mate is returned, in which the defender chooses the replies delaying the mate longest.
+    /// The search is exhaustive, so it's practical only for short mates.
+    pub fn solve_mate(&self, max_ply: usize) -> Option<Vec<Move>> {
+        let mut pos = self.clone();
+        pos.shortest_mate(max_ply)
+    }
+    /// Returns a move of the side to move which checkmates the opponent at once, if any.
+    pub fn mate_in_one(&self) -> Option<Move> {
+        let mut pos = self.clone_shallow();
+        pos.mate_within(1).map(|line| line[0])
+    }
+    /// Returns `true` if the opponent would have a mate in one if the side to move passed (null move).
+    ///
+    /// This is meaningful only when the side to move is not in check.
+    pub fn is_mate_threat(&self) -> bool {
+        self.with_side_flipped().mate_in_one().is_some()
+    }
+    fn shortest_mate(&mut self, max_ply: usize) -> Option<Vec<Move>> {
+        (1..=max_ply)
+            .step_by(2)
+            .find_map(|depth| self.mate_within(depth))
+    }
+    fn mate_within(&mut self, depth: usize) -> Option<Vec<Move>> {
+        for m in self.legal_moves() {
+            if !self.is_check_move(m) {
+                continue;
+            }
+            self.do_move(m);
+            let replies = self.legal_moves();
+            let mut line = if replies.is_empty() {
+                Some(Vec::new())
+            } else if depth >= 3 {
+                self.longest_defense(&replies, depth - 2)
+            } else {
+                None
+            };
+            self.undo_move(m);
+            if let Some(line) = line.as_mut() {
+                line.insert(0, m);
+            }
+            if line.is_some() {
+                return line;
+            }
+        }
+        None
+    }
+    fn longest_defense(&mut self, replies: &[Move], depth: usize) -> Option<Vec<Move>> {
+        let mut longest: Option<Vec<Move>> = None;
+        for &r in replies {
+            self.do_move(r);
+            let line = self.shortest_mate(depth);
+            self.undo_move(r);
+            let mut line = line?;
+            line.insert(0, r);
+            if longest.as_ref().is_none_or(|l| l.len() < line.len()) {
+                longest = Some(line);
+            }
+        }
+        longest
+    }
+}
+
+/// Solves each of the `(SFEN, mate length)` entries with [`Position::solve_mate`],
+/// searching up to the given length.
+///
+/// An entry yields `None` if its SFEN is invalid or no mate is found.
+pub fn run_tsume_suite(entries: &[(&str, usize)]) -> Vec<Option<Vec<Move>>> {
+    entries
+        .iter()
+        .map(|&(sfen, len)| Position::from_sfen(sfen).ok()?.solve_mate(len))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shogi_core::{Color, Piece, Square};
+
+    const PROBLEMS: [(&str, usize); 4] = [
+        // 頭金
+        ("8k/9/7P1/9/9/9/9/9/9 b GG2r2b2g4s4n4l17p 1", 1),
+        ("8k/9/7P1/9/9/9/9/9/9 b RSr2b4g3s4n4l17p 1", 3),
+        ("8k/9/7P1/9/9/9/9/9/9 b BS2rb4g3s4n4l17p 1", 3),
+        ("8k/9/6S2/9/9/9/9/9/9 b RBrb4g3s4n4l18p 1", 5),
+    ];
+
+    #[test]
+    fn tsume_suite() {
+        let results = run_tsume_suite(&PROBLEMS);
+        for (&(sfen, len), result) in PROBLEMS.iter().zip(results) {
+            let moves = result.expect("no mate found");
+            assert_eq!(len, moves.len(), "{sfen}");
+            let mut pos = Position::from_sfen(sfen).expect("failed to parse");
+            for m in moves {
+                assert!(pos.legal_moves().contains(&m), "{sfen}");
+                pos.do_move(m);
+            }
+            assert!(pos.in_check() && pos.legal_moves().is_empty(), "{sfen}");
+            // no shorter mate
+            let pos = Position::from_sfen(sfen).expect("failed to parse");
+            assert_eq!(None, pos.solve_mate(len.saturating_sub(2)));
+        }
+        assert_eq!(vec![None], run_tsume_suite(&[("invalid", 1)]));
+    }
+
+    #[test]
+    fn solve_mate() {
+        let pos = Position::from_sfen("8k/9/7P1/9/9/9/9/9/9 b RSr2b4g3s4n4l17p 1")
+            .expect("failed to parse");
+        assert_eq!(None, pos.solve_mate(1));
+        // ▲2二銀 △1二玉 ▲1三飛
+        assert_eq!(
+            Some(vec![
+                Move::Drop {
+                    to: Square::SQ_2B,
+                    piece: Piece::B_S,
+                },
+                Move::Normal {
+                    from: Square::SQ_1A,
+                    to: Square::SQ_1B,
+                    promote: false,
+                },
+                Move::Drop {
+                    to: Square::SQ_1C,
+                    piece: Piece::B_R,
+                },
+            ]),
+            pos.solve_mate(3)
+        );
+        assert!(Position::default().solve_mate(3).is_none());
+    }
+
+    #[test]
+    fn mate_in_one() {
+        let pos = Position::from_sfen(PROBLEMS[0].0).expect("failed to parse");
+        assert_eq!(
+            Some(Move::Drop {
+                to: Square::SQ_2B,
+                piece: Piece::B_G,
+            }),
+            pos.mate_in_one()
+        );
+        let pos = Position::from_sfen(PROBLEMS[1].0).expect("failed to parse");
+        assert_eq!(None, pos.mate_in_one());
+    }
+
+    #[test]
+    fn is_mate_threat() {
+        assert!(!Position::default().is_mate_threat());
+        // 後手に△5八金打からの詰みがある
+        let pos = Position::from_sfen("4k4/9/9/9/9/9/4g4/9/4K4 b g 1").expect("failed to parse");
+        assert!(!pos.in_check());
+        assert!(pos.is_mate_threat());
+        // 局面は変わらない
+        assert_eq!(Color::Black, pos.side_to_move());
+        assert_eq!(None, pos.mate_in_one());
+        // 持ち駒がなければ詰めろではない
+        let pos = Position::from_sfen("4k4/9/9/9/9/9/4g4/9/4K4 b - 1").expect("failed to parse");
+        assert!(!pos.is_mate_threat());
+    }
+}
@@ -1,45 +1,739 @@
 use crate::bitboard::{Bitboard, Occupied};
-use crate::tables::{ATTACK_TABLE, BETWEEN_TABLE, PROMOTABLE, RELATIVE_RANKS};
-use crate::Position;
+use crate::tables::{ATTACK_TABLE, BETWEEN_TABLE, RELATIVE_RANKS, VACANT_FILES};
+use crate::{PieceExt, Position};
 use arrayvec::ArrayVec;
-use shogi_core::{Color, Hand, Move, Piece, PieceKind, Square};
+use shogi_core::{Color, Hand, Move, Piece, PieceKind, Square, ToUsi};
+use std::fmt;
+use std::ops::ControlFlow;
 
-const MAX_LEGAL_MOVES: usize = 593;
+/// The maximum number of legal moves in a shogi position.
+///
+/// 593 is the known upper bound, reached by the position
+/// `R8/2K1S1SSk/4B4/9/9/9/9/9/1L1L1L3 b RBGSNLP3g3n17p 1`.
+pub const MAX_LEGAL_MOVES: usize = 593;
+
+/// Piece kinds in the order of the generated drops.
+const DROP_ORDER: [PieceKind; 7] = [
+    PieceKind::Pawn,
+    PieceKind::Lance,
+    PieceKind::Knight,
+    PieceKind::Silver,
+    PieceKind::Gold,
+    PieceKind::Bishop,
+    PieceKind::Rook,
+];
+
+/// A list of moves which can hold all legal moves of any position.
+pub type MoveList = ArrayVec<Move, MAX_LEGAL_MOVES>;
+
+/// Counts of moves reported by [`Position::generate_with_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GenStats {
+    /// Pseudo-legal moves of pieces on the board.
+    pub board_moves: u32,
+    /// Pseudo-legal drops.
+    pub drops: u32,
+    /// Pseudo-legal promoting moves, also counted in `board_moves`.
+    pub promotions: u32,
+    /// Pseudo-legal moves rejected by the legality check.
+    pub rejected: u32,
+}
+
+/// The reason why a drop is illegal, reported by [`Position::drop_rejection`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropRejection {
+    /// The side to move has no such piece in hand.
+    NotInHand,
+    /// The destination is occupied.
+    Occupied,
+    /// The piece would have no further move (行き所のない駒),
+    /// e.g. a pawn on the last rank or a knight on the last two ranks.
+    LastRank,
+    /// There is already an unpromoted pawn of the side to move on the file (二歩).
+    Nifu,
+    /// The king of the side to move would remain in check.
+    InCheck,
+    /// The pawn drop would checkmate (打ち歩詰め).
+    Uchifuzume,
+}
+
+impl fmt::Display for DropRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DropRejection::NotInHand => f.write_str("no such piece in hand"),
+            DropRejection::Occupied => f.write_str("the square is occupied"),
+            DropRejection::LastRank => f.write_str("the piece could never move"),
+            DropRejection::Nifu => f.write_str("two pawns on a file"),
+            DropRejection::InCheck => f.write_str("the king would remain in check"),
+            DropRejection::Uchifuzume => f.write_str("checkmate by a pawn drop"),
+        }
+    }
+}
+
+/// Receives pseudo-legal moves from the generator.
+pub(crate) trait MoveSink {
+    fn push(&mut self, m: Move);
+}
+
+impl<const CAP: usize> MoveSink for ArrayVec<Move, CAP> {
+    #[inline(always)]
+    fn push(&mut self, m: Move) {
+        ArrayVec::push(self, m);
+    }
+}
+
+/// Counts legal board moves and drops without storing them.
+struct MobilityCounter<'a> {
+    pos: &'a Position,
+    moves: u32,
+    drops: u32,
+}
+
+impl MoveSink for MobilityCounter<'_> {
+    #[inline(always)]
+    fn push(&mut self, m: Move) {
+        if self.pos.is_legal(m) {
+            if m.is_drop() {
+                self.drops += 1;
+            } else {
+                self.moves += 1;
+            }
+        }
+    }
+}
+
+/// Counts the moves without storing them or checking their legality.
+struct MoveCounter(u32);
+
+impl MoveSink for MoveCounter {
+    #[inline(always)]
+    fn push(&mut self, _: Move) {
+        self.0 += 1;
+    }
+}
+
+/// Calls the visitor with each legal move until it breaks.
+struct LegalVisitor<'a, F> {
+    pos: &'a Position,
+    verify: Bitboard,
+    f: F,
+    done: bool,
+}
+
+impl<F: FnMut(Move) -> ControlFlow<()>> MoveSink for LegalVisitor<'_, F> {
+    #[inline(always)]
+    fn push(&mut self, m: Move) {
+        if self.done {
+            return;
+        }
+        if (m.from().is_none_or(|from| !self.verify.contains(from)) || self.pos.is_legal(m))
+            && (self.f)(m).is_break()
+        {
+            self.done = true;
+        }
+    }
+}
+
+/// Passes only moves from the square through to the inner sink.
+struct SourceFilter<'a, S>(Square, &'a mut S);
+
+impl<S: MoveSink> MoveSink for SourceFilter<'_, S> {
+    #[inline(always)]
+    fn push(&mut self, m: Move) {
+        if m.from() == Some(self.0) {
+            self.1.push(m);
+        }
+    }
+}
+
+/// Passes only moves within the region, on both the source and the destination, through to the inner sink.
+struct RegionFilter<'a, S>(Bitboard, &'a mut S);
+
+impl<S: MoveSink> MoveSink for RegionFilter<'_, S> {
+    #[inline(always)]
+    fn push(&mut self, m: Move) {
+        if self.0.contains(m.to()) && m.from().is_none_or(|from| self.0.contains(from)) {
+            self.1.push(m);
+        }
+    }
+}
+
+/// Passes only promoting moves through to the inner sink.
+struct PromotionFilter<'a, S>(&'a mut S);
+
+impl<S: MoveSink> MoveSink for PromotionFilter<'_, S> {
+    #[inline(always)]
+    fn push(&mut self, m: Move) {
+        if m.is_promoting() {
+            self.0.push(m);
+        }
+    }
+}
+
+/// A set of moves stored as a bitset, for constant-time lookups.
+struct MoveSet([u64; MoveSet::WORDS]);
+
+impl MoveSet {
+    // 盤上の手 (移動元 × 移動先 × 成/不成) の後に駒打ち (駒 × 打つ位置) を並べる
+    const DROP_OFFSET: usize = Square::NUM * Square::NUM * 2;
+    const WORDS: usize = (Self::DROP_OFFSET + 32 * Square::NUM).div_ceil(64);
+
+    fn new() -> Self {
+        Self([0; Self::WORDS])
+    }
+    #[inline(always)]
+    fn index(m: Move) -> usize {
+        match m {
+            Move::Normal { from, to, promote } => {
+                (from.array_index() * Square::NUM + to.array_index()) * 2 + promote as usize
+            }
+            Move::Drop { piece, to } => {
+                Self::DROP_OFFSET + piece.as_u8() as usize * Square::NUM + to.array_index()
+            }
+        }
+    }
+    /// Removes `m` and returns `true` if it was in the set.
+    #[inline(always)]
+    fn remove(&mut self, m: Move) -> bool {
+        let index = Self::index(m);
+        let bit = 1 << (index % 64);
+        let word = &mut self.0[index / 64];
+        let contained = *word & bit != 0;
+        *word &= !bit;
+        contained
+    }
+}
+
+impl MoveSink for MoveSet {
+    #[inline(always)]
+    fn push(&mut self, m: Move) {
+        let index = Self::index(m);
+        self.0[index / 64] |= 1 << (index % 64);
+    }
+}
 
 impl Position {
-    pub fn legal_moves(&self) -> ArrayVec<Move, MAX_LEGAL_MOVES> {
-        let mut av = ArrayVec::new();
+    /// Returns the legal moves.
+    ///
+    /// Pawn drops giving checkmate (打ち歩詰め) are excluded,
+    /// while pawn moves on the board giving checkmate are legal and included.
+    ///
+    /// The drops come after the board moves, ordered by the piece kind (P, L, N, S, G, B, R)
+    /// and then by [`Square::array_index`] of the destination.
+    pub fn legal_moves(&self) -> MoveList {
+        let mut av = MoveList::new();
+        self.generate(&mut av);
+        self.retain_legal(&mut av);
+        av
+    }
+    /// Returns the moves of `moves` which are legal in the position, in the input order.
+    ///
+    /// Only the first occurrence of a duplicated move is kept, so the result always fits
+    /// in a [`MoveList`] whatever `moves` contains.
+    pub fn filter_legal(&self, moves: &[Move]) -> MoveList {
+        let mut pseudo = MoveSet::new();
+        self.generate(&mut pseudo);
+        let verify = self.needs_verification();
+        let mut av = MoveList::new();
+        for &m in moves {
+            // 一度見た手は集合から取り除き、重複を出力しない
+            if pseudo.remove(m)
+                && (m.from().is_none_or(|from| !verify.contains(from)) || self.is_legal(m))
+            {
+                av.push(m);
+            }
+        }
+        av
+    }
+    /// Returns the legal moves formatted in USI.
+    pub fn legal_moves_usi(&self) -> Vec<String> {
+        self.legal_moves()
+            .iter()
+            .map(|m| m.to_usi_owned())
+            .collect()
+    }
+    /// Returns the legal moves together with the statistics of the generation, for debugging.
+    pub fn generate_with_stats(&self) -> (MoveList, GenStats) {
+        let mut av = MoveList::new();
+        self.generate(&mut av);
+        let mut stats = GenStats::default();
+        for m in &av {
+            if m.is_drop() {
+                stats.drops += 1;
+            } else {
+                stats.board_moves += 1;
+                if m.is_promoting() {
+                    stats.promotions += 1;
+                }
+            }
+        }
+        let len = av.len();
+        self.retain_legal(&mut av);
+        stats.rejected = (len - av.len()) as u32;
+        (av, stats)
+    }
+    /// Returns the numbers of legal moves by the kind of the moving piece, for debugging.
+    ///
+    /// Moves are counted by the kind on the source square, whether promoting or not,
+    /// and drops by the kind of the dropped piece.
+    pub fn legal_move_counts_by_piece(&self) -> [usize; PieceKind::NUM] {
+        let mut counts = [0; PieceKind::NUM];
+        self.for_each_legal(|m| {
+            let pk = match m {
+                Move::Normal { from, .. } => self.piece_at(from).unwrap().piece_kind(),
+                Move::Drop { piece, .. } => piece.piece_kind(),
+            };
+            counts[pk.array_index()] += 1;
+            ControlFlow::Continue(())
+        });
+        counts
+    }
+    /// Calls `f` with each legal move without storing them.
+    ///
+    /// `f` is not called any more once it returns [`ControlFlow::Break`].
+    pub fn for_each_legal<F: FnMut(Move) -> ControlFlow<()>>(&self, f: F) {
+        let mut visitor = LegalVisitor {
+            pos: self,
+            verify: self.needs_verification(),
+            f,
+            done: false,
+        };
+        self.generate(&mut visitor);
+    }
+    /// Returns the legal moves which capture a piece.
+    pub fn capture_moves(&self) -> MoveList {
+        let mut av = MoveList::new();
         if self.in_check() {
             self.generate_evasions(&mut av);
+            av.retain(|m| self.piece_at(m.to()).is_some());
         } else {
-            self.generate_all(&mut av);
+            let target = self.player_bitboard(self.side_to_move().flip());
+            self.generate_board_moves(&mut av, &target);
         }
-
-        let mut i = 0;
-        while i != av.len() {
-            if self.is_legal(av[i]) {
-                i += 1;
+        self.retain_legal(&mut av);
+        av
+    }
+    /// Returns the legal moves which don't capture a piece.
+    ///
+    /// Non-capturing promotions are quiet. Drops are included only if `include_drops` is `true`.
+    pub fn quiet_moves(&self, include_drops: bool) -> MoveList {
+        let mut av = MoveList::new();
+        if self.in_check() {
+            self.generate_evasions(&mut av);
+            av.retain(|m| self.piece_at(m.to()).is_none() && (include_drops || !m.is_drop()));
+        } else {
+            let target = !self.occupied_bitboard() & !Bitboard::empty();
+            self.generate_board_moves(&mut av, &target);
+            if include_drops {
+                self.generate_drop(&mut av, &target);
+            }
+        }
+        self.retain_legal(&mut av);
+        av
+    }
+    /// Returns the legal moves in a stable order that doesn't depend on the generator.
+    ///
+    /// Normal moves come first, ordered by `(from, to, promote)`,
+    /// followed by drops ordered by `(piece, to)`.
+    pub fn legal_moves_sorted(&self) -> MoveList {
+        let mut av = self.legal_moves();
+        av.sort_unstable();
+        av
+    }
+    /// Returns the numbers of legal board moves and drops of `c`.
+    ///
+    /// If `c` is not the side to move, the moves are counted as if `c` were to move.
+    pub fn mobility(&self, c: Color) -> (u32, u32) {
+        if c != self.side_to_move() {
+            return self.with_side_flipped().mobility(c);
+        }
+        let mut counter = MobilityCounter {
+            pos: self,
+            moves: 0,
+            drops: 0,
+        };
+        self.generate(&mut counter);
+        (counter.moves, counter.drops)
+    }
+    /// Returns the number of legal moves of the side to move
+    /// and the number of pseudo-legal moves of the opponent.
+    ///
+    /// The opponent's moves are counted as if it were to move in the same position,
+    /// including drops, and are not checked for leaving its own king in check.
+    ///
+    /// Both counts come from a single pass over the pieces on the board, sharing the occupancy
+    /// and the attacks of each piece. Only the moves of the king and the pinned pieces of the side
+    /// to move are verified, and its evasions are generated separately when it's in check.
+    pub fn both_mobility(&self) -> (u32, u32) {
+        let us = self.side_to_move();
+        let in_check = self.in_check();
+        let occ = self.occupied_bitboard();
+        let verify = self.needs_verification();
+        let mut counts = [0; Color::NUM];
+        for from in occ {
+            let (pk, c) = self.piece_at(from).unwrap().to_parts();
+            if c == us && in_check {
+                continue;
+            }
+            let verified = c == us && verify.contains(from);
+            for to in ATTACK_TABLE.attack(pk, from, c, &occ) & !self.player_bitboard(c) {
+                let (can_promote, can_stay) = self.promotion_options(pk, c, from, to);
+                for (promote, allowed) in [(true, can_promote), (false, can_stay)] {
+                    if allowed && (!verified || self.is_legal(Move::Normal { from, to, promote })) {
+                        counts[c.array_index()] += 1;
+                    }
+                }
+            }
+        }
+        for c in Color::all() {
+            if c == us && in_check {
+                continue;
+            }
+            let mut counter = MoveCounter(0);
+            let hand = self.hand(c);
+            for pk in DROP_ORDER
+                .into_iter()
+                .filter(|&pk| hand.count(pk).unwrap_or_default() > 0)
+            {
+                self.generate_drop_of(&mut counter, c, pk, &!occ);
+            }
+            counts[c.array_index()] += counter.0;
+        }
+        if in_check {
+            counts[us.array_index()] = self.legal_moves().len() as u32;
+        }
+        (counts[us.array_index()], counts[us.flip().array_index()])
+    }
+    fn generate(&self, av: &mut impl MoveSink) {
+        if self.in_check() {
+            self.generate_evasions(av);
+        } else {
+            self.generate_all(av);
+        }
+    }
+    /// Returns the legal moves which promote.
+    pub fn promotion_moves(&self) -> MoveList {
+        let mut av = MoveList::new();
+        if self.in_check() {
+            self.generate_evasions(&mut PromotionFilter(&mut av));
+        } else {
+            let target = !self.player_bitboard(self.side_to_move());
+            self.generate_board_moves(&mut PromotionFilter(&mut av), &target);
+        }
+        self.retain_legal(&mut av);
+        av
+    }
+    /// Returns the legal moves which promote a piece of `pk`.
+    pub fn promotion_moves_of(&self, pk: PieceKind) -> MoveList {
+        let mut av = self.promotion_moves();
+        av.retain(|m| {
+            m.from()
+                .and_then(|from| self.piece_at(from))
+                .is_some_and(|p| p.piece_kind() == pk)
+        });
+        av
+    }
+    /// Returns `true` if the side to move is in check and `m` is a legal move, which resolves the check.
+    pub fn is_check_evasion(&self, m: Move) -> bool {
+        if !self.in_check() {
+            return false;
+        }
+        let mut av = MoveList::new();
+        self.generate_evasions(&mut av);
+        av.contains(&m) && self.is_legal(m)
+    }
+    /// Returns the legal moves of the piece on `from`, which is empty unless it's a piece of the side to move.
+    pub fn legal_moves_from(&self, from: Square) -> MoveList {
+        let mut av = MoveList::new();
+        if self
+            .piece_at(from)
+            .is_some_and(|p| p.color() == self.side_to_move())
+        {
+            self.generate(&mut SourceFilter(from, &mut av));
+            self.retain_legal(&mut av);
+        }
+        av
+    }
+    /// Returns the squares of the pieces of kind `pk` of the side to move which can legally move to `to`.
+    ///
+    /// If it has more than one square, notations need the source square to tell the moves apart.
+    pub fn ambiguous_movers(&self, pk: PieceKind, to: Square) -> Bitboard {
+        self.movers_in(&self.legal_moves(), pk, to)
+    }
+    /// Same as [`Position::ambiguous_movers`], looking for the moves in `moves` instead of
+    /// generating the legal moves.
+    pub(crate) fn movers_in(&self, moves: &[Move], pk: PieceKind, to: Square) -> Bitboard {
+        let mut bb = Bitboard::empty();
+        for &m in moves {
+            if let Move::Normal { from, to: t, .. } = m {
+                if t == to && self.piece_at(from).is_some_and(|p| p.piece_kind() == pk) {
+                    bb |= Bitboard::single(from);
+                }
+            }
+        }
+        bb
+    }
+    /// Returns the legal moves whose source and destination are both in `region`,
+    /// and the legal drops onto `region`.
+    pub fn legal_moves_in_region(&self, region: Bitboard) -> MoveList {
+        let mut av = MoveList::new();
+        let mut sink = RegionFilter(region, &mut av);
+        if self.in_check() {
+            self.generate_evasions(&mut sink);
+        } else {
+            let target = !self.player_bitboard(self.side_to_move()) & region;
+            self.generate_board_moves(&mut sink, &target);
+            self.generate_drop(&mut sink, &(!self.occupied_bitboard() & region));
+        }
+        self.retain_legal(&mut av);
+        av
+    }
+    /// Returns why dropping `pk` of the side to move on `to` is illegal, or `None` if it's legal.
+    ///
+    /// If several reasons apply, the first one in the order of [`DropRejection`] is returned.
+    pub fn drop_rejection(&self, pk: PieceKind, to: Square) -> Option<DropRejection> {
+        let c = self.side_to_move();
+        if !Hand::is_hand_piece(pk) || self.hand(c).count(pk).unwrap_or_default() == 0 {
+            return Some(DropRejection::NotInHand);
+        }
+        if self.piece_at(to).is_some() {
+            return Some(DropRejection::Occupied);
+        }
+        if Piece::new(pk, c).must_promote(to) {
+            return Some(DropRejection::LastRank);
+        }
+        if pk == PieceKind::Pawn && self.pawn_files(c) & (1 << (to.file() - 1)) != 0 {
+            return Some(DropRejection::Nifu);
+        }
+        if self.in_check() {
+            let king = self.king_position(c)?;
+            let mut checkers = self.checkers().into_iter();
+            let interposes = match (checkers.next(), checkers.next()) {
+                (Some(ch), None) => {
+                    BETWEEN_TABLE[ch.array_index()][king.array_index()].contains(to)
+                }
+                _ => false,
+            };
+            if !interposes {
+                return Some(DropRejection::InCheck);
+            }
+        }
+        if pk == PieceKind::Pawn
+            && self
+                .king_position(c.flip())
+                .is_some_and(|king| ATTACK_TABLE.fu.attack(to, c).contains(king))
+            && self.is_pawn_drop_mate(to)
+        {
+            return Some(DropRejection::Uchifuzume);
+        }
+        None
+    }
+    /// Returns the legal moves which give check to the opponent, including drops.
+    ///
+    /// The moves are generated from [`Position::check_squares`] of the opponent: the moves to
+    /// the squares from which the moved piece gives check, and the moves of the pieces blocking
+    /// a sliding piece of the side to move which leave its line (開き王手).
+    pub fn checking_moves(&self) -> MoveList {
+        let mut av = MoveList::new();
+        let c = self.side_to_move();
+        let Some(king) = self.king_position(c.flip()) else {
+            return av;
+        };
+        let own = self.player_bitboard(c);
+        // 王手されている場合、玉以外の駒は王手を防ぐ地点にのみ動ける
+        let target = if self.in_check() {
+            match (self.king_position(c), self.checkers().count()) {
+                (Some(k), 1) => {
+                    let ch = self.checkers().into_iter().next().unwrap();
+                    BETWEEN_TABLE[ch.array_index()][k.array_index()] | self.checkers()
+                }
+                _ => Bitboard::empty(),
+            }
+        } else {
+            !own
+        };
+        let checkables = self.check_squares(c.flip());
+        let discoverers = self.pinned(c.flip()) & own;
+        let occ = self.occupied_bitboard();
+        for from in own {
+            let pk = self.piece_at(from).unwrap().piece_kind();
+            let target = if pk == PieceKind::King { !own } else { target };
+            let attacks = ATTACK_TABLE.attack(pk, from, c, &occ) & target;
+            let promoted = pk.promote();
+            let is_discoverer = discoverers.contains(from);
+            let candidates = if is_discoverer {
+                attacks
             } else {
-                av.swap_remove(i);
+                let promoted_checkables =
+                    promoted.map_or(Bitboard::empty(), |pk| checkables[pk.array_index()]);
+                attacks & (checkables[pk.array_index()] | promoted_checkables)
+            };
+            for to in candidates {
+                let discovered = is_discoverer
+                    && !(BETWEEN_TABLE[king.array_index()][from.array_index()].contains(to)
+                        || BETWEEN_TABLE[king.array_index()][to.array_index()].contains(from));
+                let (can_promote, can_stay) = self.promotion_options(pk, c, from, to);
+                if let Some(promoted) = promoted.filter(|_| can_promote) {
+                    if discovered || checkables[promoted.array_index()].contains(to) {
+                        av.push(Move::Normal {
+                            from,
+                            to,
+                            promote: true,
+                        });
+                    }
+                }
+                if can_stay && (discovered || checkables[pk.array_index()].contains(to)) {
+                    av.push(Move::Normal {
+                        from,
+                        to,
+                        promote: false,
+                    });
+                }
+            }
+        }
+        self.retain_legal(&mut av);
+        av.extend(self.checking_drops());
+        av
+    }
+    /// Returns the legal drops which give check to the opponent.
+    ///
+    /// Each piece in hand is dropped onto its [`Position::check_squares`] of the opponent
+    /// which are empty, and which block the check if the side to move is in check.
+    /// Pawn drops giving checkmate (打ち歩詰め) are excluded as in [`Position::legal_moves`].
+    pub fn checking_drops(&self) -> MoveList {
+        let mut av = MoveList::new();
+        let mut target = !self.occupied_bitboard();
+        // 王手されている場合は合駒になる地点のみ
+        if self.in_check() {
+            let (Some(king), 1) = (
+                self.king_position(self.side_to_move()),
+                self.checkers().count(),
+            ) else {
+                return av;
+            };
+            let ch = self.checkers().into_iter().next().unwrap();
+            target &= BETWEEN_TABLE[ch.array_index()][king.array_index()];
+        }
+        let checkables = self.check_squares(self.side_to_move().flip());
+        let hand = self.hand(self.side_to_move());
+        for pk in DROP_ORDER
+            .into_iter()
+            .filter(|&pk| hand.count(pk).unwrap_or_default() > 0)
+        {
+            let target = target & checkables[pk.array_index()];
+            self.generate_drop_of(&mut av, self.side_to_move(), pk, &target);
+        }
+        av
+    }
+    /// Returns the legal moves, excluding those which would make a draw by repetition (千日手).
+    ///
+    /// If every legal move leads to the repetition, all of them are returned.
+    pub fn legal_moves_avoiding_repetition(&self) -> MoveList {
+        let moves = self.legal_moves();
+        let mut pos = self.clone();
+        let mut av = MoveList::new();
+        for &m in &moves {
+            pos.do_move(m);
+            if !pos.can_claim_repetition_draw() {
+                av.push(m);
+            }
+            pos.undo_move(m);
+        }
+        if av.is_empty() {
+            moves
+        } else {
+            av
+        }
+    }
+    /// Returns the legal moves of the king of the side to move.
+    pub fn king_moves(&self) -> MoveList {
+        let mut av = MoveList::new();
+        let c = self.side_to_move();
+        if let Some(from) = self.king_position(c) {
+            for to in self.king_destinations(c, from) {
+                av.push(Move::Normal {
+                    from,
+                    to,
+                    promote: false,
+                });
             }
         }
         av
     }
-    fn generate_all(&self, av: &mut ArrayVec<Move, MAX_LEGAL_MOVES>) {
+    /// Returns `true` if the king of `c` can move to a safe square in the promotion zone
+    /// or one rank closer to it, as a hint toward entering king (入玉).
+    ///
+    /// This looks only one move ahead and ignores whose turn it is.
+    pub fn can_enter_king(&self, c: Color) -> bool {
+        let Some(from) = self.king_position(c) else {
+            return false;
+        };
+        let rank = |sq: Square| RELATIVE_RANKS[sq.array_index()][c.array_index()];
+        self.king_destinations(c, from)
+            .into_iter()
+            .any(|to| self.promotable(to, c) || rank(to) < rank(from))
+    }
+    /// Returns the squares which the king of `c` on `from` can move to without being attacked.
+    fn king_destinations(&self, c: Color, from: Square) -> Bitboard {
+        // 玉自身が飛び駒の利きを遮らないようにする
+        let occ = self.occupied_bitboard() ^ Bitboard::single(from);
+        let mut bb = Bitboard::empty();
+        for to in ATTACK_TABLE.ou.attack(from, c) & !self.player_bitboard(c) {
+            if self.attackers_to(c.flip(), to, &occ).is_empty() {
+                bb |= Bitboard::single(to);
+            }
+        }
+        bb
+    }
+    /// Returns whether a piece of `pk` and `c` moving from `from` to `to` can promote,
+    /// and whether it can stay unpromoted, as the generators do.
+    #[inline(always)]
+    fn promotion_options(&self, pk: PieceKind, c: Color, from: Square, to: Square) -> (bool, bool) {
+        let can_promote =
+            pk.promote().is_some() && (self.promotable(from, c) || self.promotable(to, c));
+        let must_promote = can_promote
+            && match pk {
+                PieceKind::Pawn | PieceKind::Lance => {
+                    RELATIVE_RANKS[to.array_index()][c.array_index()] <= 1
+                }
+                PieceKind::Knight => RELATIVE_RANKS[to.array_index()][c.array_index()] <= 2,
+                _ => false,
+            };
+        (can_promote, !must_promote)
+    }
+    /// Removes the illegal moves from `av`, keeping the order of the rest.
+    fn retain_legal(&self, av: &mut MoveList) {
+        let verify = self.needs_verification();
+        av.retain(|m| m.from().is_none_or(|from| !verify.contains(from)) || self.is_legal(*m));
+    }
+    /// Returns the squares of the pieces whose pseudo-legal moves may be illegal.
+    fn needs_verification(&self) -> Bitboard {
+        // 玉と pin された駒以外の指し手は検証不要
+        let c = self.side_to_move();
+        let mut verify = self.pinned(c);
+        if let Some(sq) = self.king_position(c) {
+            verify |= Bitboard::single(sq);
+        }
+        verify
+    }
+    fn generate_all(&self, av: &mut impl MoveSink) {
         let target = !self.player_bitboard(self.side_to_move());
-        self.generate_for_fu(av, &target);
-        self.generate_for_ky(av, &target);
-        self.generate_for_ke(av, &target);
-        self.generate_for_gi(av, &target);
-        self.generate_for_ka(av, &target);
-        self.generate_for_hi(av, &target);
-        self.generate_for_ki(av, &target);
-        self.generate_for_ou(av, &target);
-        self.generate_for_um(av, &target);
-        self.generate_for_ry(av, &target);
+        self.generate_board_moves(av, &target);
         self.generate_drop(av, &(!self.occupied_bitboard() & !Bitboard::empty()));
     }
-    fn generate_evasions(&self, av: &mut ArrayVec<Move, MAX_LEGAL_MOVES>) {
+    fn generate_board_moves(&self, av: &mut impl MoveSink, target: &Bitboard) {
+        self.generate_for_fu(av, target);
+        self.generate_for_ky(av, target);
+        self.generate_for_ke(av, target);
+        self.generate_for_gi(av, target);
+        self.generate_for_ka(av, target);
+        self.generate_for_hi(av, target);
+        self.generate_for_ki(av, target);
+        self.generate_for_ou(av, target);
+        self.generate_for_um(av, target);
+        self.generate_for_ry(av, target);
+    }
+    fn generate_evasions(&self, av: &mut impl MoveSink) {
         let c = self.side_to_move();
         if let Some(king) = self.king_position(c) {
             let mut checkers_attacks = Bitboard::empty();
@@ -87,7 +781,7 @@ impl Position {
             }
         }
     }
-    fn generate_for_fu(&self, av: &mut ArrayVec<Move, MAX_LEGAL_MOVES>, target: &Bitboard) {
+    fn generate_for_fu(&self, av: &mut impl MoveSink, target: &Bitboard) {
         let c = self.side_to_move();
         let (to_bb, delta) = [
             (self.piece_bitboard(Piece::B_P).shr(), 1),
@@ -95,7 +789,7 @@ impl Position {
         ][c.array_index()];
         for to in to_bb & target {
             let from = unsafe { Square::from_u8_unchecked(to.index().wrapping_add(delta)) };
-            if PROMOTABLE[to.array_index()][c.array_index()] {
+            if self.promotable(to, c) {
                 av.push(Move::Normal {
                     from,
                     to,
@@ -117,11 +811,11 @@ impl Position {
             }
         }
     }
-    fn generate_for_ky(&self, av: &mut ArrayVec<Move, MAX_LEGAL_MOVES>, target: &Bitboard) {
+    fn generate_for_ky(&self, av: &mut impl MoveSink, target: &Bitboard) {
         let c = self.side_to_move();
         for from in self.player_bitboard(c) & self.piece_kind_bitboard(PieceKind::Lance) {
             for to in ATTACK_TABLE.ky.attack(from, c, &self.occupied_bitboard()) & target {
-                if PROMOTABLE[to.array_index()][c.array_index()] {
+                if self.promotable(to, c) {
                     av.push(Move::Normal {
                         from,
                         to,
@@ -144,11 +838,11 @@ impl Position {
             }
         }
     }
-    fn generate_for_ke(&self, av: &mut ArrayVec<Move, MAX_LEGAL_MOVES>, target: &Bitboard) {
+    fn generate_for_ke(&self, av: &mut impl MoveSink, target: &Bitboard) {
         let c = self.side_to_move();
         for from in self.player_bitboard(c) & self.piece_kind_bitboard(PieceKind::Knight) {
             for to in ATTACK_TABLE.ke.attack(from, c) & target {
-                if PROMOTABLE[to.array_index()][c.array_index()] {
+                if self.promotable(to, c) {
                     av.push(Move::Normal {
                         from,
                         to,
@@ -171,17 +865,17 @@ impl Position {
             }
         }
     }
-    fn generate_for_gi(&self, av: &mut ArrayVec<Move, MAX_LEGAL_MOVES>, target: &Bitboard) {
+    fn generate_for_gi(&self, av: &mut impl MoveSink, target: &Bitboard) {
         let c = self.side_to_move();
         for from in self.player_bitboard(c) & self.piece_kind_bitboard(PieceKind::Silver) {
-            let from_is_opponent_field = PROMOTABLE[from.array_index()][c.array_index()];
+            let from_is_opponent_field = self.promotable(from, c);
             for to in ATTACK_TABLE.gi.attack(from, c) & target {
                 av.push(Move::Normal {
                     from,
                     to,
                     promote: false,
                 });
-                if from_is_opponent_field || PROMOTABLE[to.array_index()][c.array_index()] {
+                if from_is_opponent_field || self.promotable(to, c) {
                     av.push(Move::Normal {
                         from,
                         to,
@@ -191,17 +885,17 @@ impl Position {
             }
         }
     }
-    fn generate_for_ka(&self, av: &mut ArrayVec<Move, MAX_LEGAL_MOVES>, target: &Bitboard) {
+    fn generate_for_ka(&self, av: &mut impl MoveSink, target: &Bitboard) {
         let c = self.side_to_move();
         for from in self.player_bitboard(c) & self.piece_kind_bitboard(PieceKind::Bishop) {
-            let from_is_opponent_field = PROMOTABLE[from.array_index()][c.array_index()];
+            let from_is_opponent_field = self.promotable(from, c);
             for to in ATTACK_TABLE.ka.attack(from, &self.occupied_bitboard()) & target {
                 av.push(Move::Normal {
                     from,
                     to,
                     promote: false,
                 });
-                if from_is_opponent_field || PROMOTABLE[to.array_index()][c.array_index()] {
+                if from_is_opponent_field || self.promotable(to, c) {
                     av.push(Move::Normal {
                         from,
                         to,
@@ -211,17 +905,17 @@ impl Position {
             }
         }
     }
-    fn generate_for_hi(&self, av: &mut ArrayVec<Move, MAX_LEGAL_MOVES>, target: &Bitboard) {
+    fn generate_for_hi(&self, av: &mut impl MoveSink, target: &Bitboard) {
         let c = self.side_to_move();
         for from in self.player_bitboard(c) & self.piece_kind_bitboard(PieceKind::Rook) {
-            let from_is_opponent_field = PROMOTABLE[from.array_index()][c.array_index()];
+            let from_is_opponent_field = self.promotable(from, c);
             for to in ATTACK_TABLE.hi.attack(from, &self.occupied_bitboard()) & target {
                 av.push(Move::Normal {
                     from,
                     to,
                     promote: false,
                 });
-                if from_is_opponent_field || PROMOTABLE[to.array_index()][c.array_index()] {
+                if from_is_opponent_field || self.promotable(to, c) {
                     av.push(Move::Normal {
                         from,
                         to,
@@ -231,7 +925,7 @@ impl Position {
             }
         }
     }
-    fn generate_for_ki(&self, av: &mut ArrayVec<Move, MAX_LEGAL_MOVES>, target: &Bitboard) {
+    fn generate_for_ki(&self, av: &mut impl MoveSink, target: &Bitboard) {
         let c = self.side_to_move();
         for from in (self.piece_kind_bitboard(PieceKind::Gold)
             | self.piece_kind_bitboard(PieceKind::ProPawn)
@@ -249,7 +943,7 @@ impl Position {
             }
         }
     }
-    fn generate_for_ou(&self, av: &mut ArrayVec<Move, MAX_LEGAL_MOVES>, target: &Bitboard) {
+    fn generate_for_ou(&self, av: &mut impl MoveSink, target: &Bitboard) {
         let c = self.side_to_move();
         for from in self.player_bitboard(c) & self.piece_kind_bitboard(PieceKind::King) {
             for to in ATTACK_TABLE.ou.attack(from, c) & target {
@@ -261,7 +955,7 @@ impl Position {
             }
         }
     }
-    fn generate_for_um(&self, av: &mut ArrayVec<Move, MAX_LEGAL_MOVES>, target: &Bitboard) {
+    fn generate_for_um(&self, av: &mut impl MoveSink, target: &Bitboard) {
         let c = self.side_to_move();
         for from in self.player_bitboard(c) & self.piece_kind_bitboard(PieceKind::ProBishop) {
             for to in (ATTACK_TABLE.ka.attack(from, &self.occupied_bitboard())
@@ -276,7 +970,7 @@ impl Position {
             }
         }
     }
-    fn generate_for_ry(&self, av: &mut ArrayVec<Move, MAX_LEGAL_MOVES>, target: &Bitboard) {
+    fn generate_for_ry(&self, av: &mut impl MoveSink, target: &Bitboard) {
         let c = self.side_to_move();
         for from in self.player_bitboard(c) & self.piece_kind_bitboard(PieceKind::ProRook) {
             for to in (ATTACK_TABLE.hi.attack(from, &self.occupied_bitboard())
@@ -291,34 +985,39 @@ impl Position {
             }
         }
     }
-    fn generate_drop(&self, av: &mut ArrayVec<Move, MAX_LEGAL_MOVES>, target: &Bitboard) {
-        let c = self.side_to_move();
+    fn generate_drop(&self, av: &mut impl MoveSink, target: &Bitboard) {
         let hand = self.hand(self.side_to_move());
-        for pk in Hand::all_hand_pieces().filter(|&pk| hand.count(pk).unwrap_or_default() > 0) {
-            let mut target = *target;
-            if pk == PieceKind::Pawn {
-                target &= (self.player_bitboard(c) & self.piece_kind_bitboard(PieceKind::Pawn))
-                    .vacant_files();
-                // 打ち歩詰めチェック
-                if let Some(sq) = self.king_position(c.flip()) {
-                    if let Some(to) = ATTACK_TABLE.fu.attack(sq, c.flip()).into_iter().next() {
-                        if target.contains(to) && self.is_pawn_drop_mate(to) {
-                            target &= !Bitboard::single(to);
-                        }
+        for pk in DROP_ORDER
+            .into_iter()
+            .filter(|&pk| hand.count(pk).unwrap_or_default() > 0)
+        {
+            self.generate_drop_of(av, self.side_to_move(), pk, target);
+        }
+    }
+    /// Generates the drops of `pk` by `c` onto `target`, assuming `pk` is in the hand of `c`.
+    fn generate_drop_of(&self, av: &mut impl MoveSink, c: Color, pk: PieceKind, target: &Bitboard) {
+        let mut target = *target;
+        if pk == PieceKind::Pawn {
+            target &= VACANT_FILES[self.pawn_files(c) as usize];
+            // 打ち歩詰めチェック
+            if let Some(sq) = self.king_position(c.flip()) {
+                if let Some(to) = ATTACK_TABLE.fu.attack(sq, c.flip()).into_iter().next() {
+                    if target.contains(to) && self.is_pawn_drop_mate_by(c, to) {
+                        target &= !Bitboard::single(to);
                     }
                 }
             }
-            let piece = Piece::new(pk, c);
-            for to in target {
-                if match pk {
-                    PieceKind::Pawn | PieceKind::Lance => {
-                        RELATIVE_RANKS[to.array_index()][c.array_index()] > 1
-                    }
-                    PieceKind::Knight => RELATIVE_RANKS[to.array_index()][c.array_index()] > 2,
-                    _ => true,
-                } {
-                    av.push(Move::Drop { to, piece });
+        }
+        let piece = Piece::new(pk, c);
+        for to in target {
+            if match pk {
+                PieceKind::Pawn | PieceKind::Lance => {
+                    RELATIVE_RANKS[to.array_index()][c.array_index()] > 1
                 }
+                PieceKind::Knight => RELATIVE_RANKS[to.array_index()][c.array_index()] > 2,
+                _ => true,
+            } {
+                av.push(Move::Drop { to, piece });
             }
         }
     }
@@ -348,7 +1047,10 @@ impl Position {
         true
     }
     fn is_pawn_drop_mate(&self, sq: Square) -> bool {
-        let c = self.side_to_move();
+        self.is_pawn_drop_mate_by(self.side_to_move(), sq)
+    }
+    /// Returns `true` if a pawn of `c` dropped on `sq` would checkmate, whoever is to move.
+    fn is_pawn_drop_mate_by(&self, c: Color, sq: Square) -> bool {
         // 玉自身が歩を取れる
         if self
             .attackers_to(c, sq, &self.occupied_bitboard())
@@ -376,7 +1078,7 @@ impl Position {
         true
     }
     #[rustfmt::skip]
-    fn attackers_to(&self, c: Color, to: Square, occ: &Bitboard) -> Bitboard {
+    pub(crate) fn attackers_to(&self, c: Color, to: Square, occ: &Bitboard) -> Bitboard {
         let opp = c.flip();
         (     (ATTACK_TABLE.fu.attack(to, opp)      & self.piece_kind_bitboard(PieceKind::Pawn))
             | (ATTACK_TABLE.ky.attack(to, opp, occ) & self.piece_kind_bitboard(PieceKind::Lance))
@@ -403,6 +1105,7 @@ impl Position {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Rules;
     use shogi_core::PartialPosition;
     use shogi_usi_parser::FromUsi;
 
@@ -413,55 +1116,370 @@ mod tests {
     }
 
     #[test]
-    fn drop_moves() {
-        // P1-KY-KE-GI-KI-OU-KI * -KE-KY
-        // P2 * -HI *  *  *  *  * -GI *
-        // P3-FU-FU-FU-FU-FU-FU * -FU-FU
-        // P4 *  *  *  *  *  * -FU *  *
-        // P5 *  *  *  *  *  *  *  *  *
-        // P6 *  * +FU *  *  *  *  *  *
-        // P7+FU+FU * +FU+FU+FU+FU+FU+FU
-        // P8 *  *  *  *  *  *  * +HI *
-        // P9+KY+KE+GI+GI+KI+OU+KI+GI+KE+KY
-        // P+00KA
-        // P-00KA
-        // +
+    fn legal_moves_sorted() {
+        let pos = Position::default();
+        let moves = pos.legal_moves_sorted();
+        assert_eq!(30, moves.len());
+        assert_eq!(
+            [
+                Move::Normal {
+                    from: Square::SQ_1G,
+                    to: Square::SQ_1F,
+                    promote: false,
+                },
+                Move::Normal {
+                    from: Square::SQ_1I,
+                    to: Square::SQ_1H,
+                    promote: false,
+                },
+            ],
+            moves[..2]
+        );
+        assert!(moves.windows(2).all(|w| w[0] < w[1]));
+
+        // drops follow all normal moves
+        let pos = Position::new(
+            PartialPosition::from_usi(
+                "sfen lnsgkg1nl/1r5s1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/7R1/LNSGKGSNL b Bb 1",
+            )
+            .expect("failed to parse"),
+        );
+        let moves = pos.legal_moves_sorted();
+        let first_drop = moves.iter().position(|m| m.is_drop()).unwrap();
+        assert!(moves[first_drop..].iter().all(|m| m.is_drop()));
+        assert!(moves.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn mobility() {
+        let pos = Position::default();
+        assert_eq!((30, 0), pos.mobility(Color::Black));
+        assert_eq!((30, 0), pos.mobility(Color::White));
+
         let pos = Position::new(
             PartialPosition::from_usi(
                 "sfen lnsgkg1nl/1r5s1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/7R1/LNSGKGSNL b Bb 1",
             )
             .expect("failed to parse"),
         );
+        let moves = pos.legal_moves();
+        let drops = moves.iter().filter(|m| m.is_drop()).count() as u32;
         assert_eq!(
-            43,
-            pos.legal_moves()
-                .into_iter()
-                .filter(|m| m.is_drop())
-                .count()
+            (moves.len() as u32 - drops, drops),
+            pos.mobility(Color::Black)
         );
+        assert_eq!(43, drops);
     }
 
     #[test]
-    fn maximum_moves() {
-        // http://lfics81.techblog.jp/archives/2041940.html
-        // P1+HI *  *  *  *  *  *  *  *
-        // P2 *  * +OU * +GI * +GI+GI-OU
-        // P3 *  *  *  * +KA *  *  *  *
-        // P4 *  *  *  *  *  *  *  *  *
-        // P5 *  *  *  *  *  *  *  *  *
-        // P6 *  *  *  *  *  *  *  *  *
-        // P7 *  *  *  *  *  *  *  *  *
-        // P8 *  *  *  *  *  *  *  *  *
-        // P9 * +KY * +KY * +KY *  *  *
-        // P+00FU00KY00KE00GI00KI00KA00HI
-        // P-00AL
-        // +
+    fn both_mobility() {
+        assert_eq!((30, 30), Position::default().both_mobility());
+
+        // 後手の金は pin されているが、動く手も数える
         let pos = Position::new(
-            PartialPosition::from_usi("sfen R8/2K1S1SSk/4B4/9/9/9/9/9/1L1L1L3 b RBGSNLP3g3n17p 1")
+            PartialPosition::from_usi("sfen 4k4/4g4/9/9/4R4/9/9/9/4K4 b - 1")
                 .expect("failed to parse"),
         );
-        assert_eq!(593, pos.legal_moves().len());
-    }
+        let (ours, theirs) = pos.both_mobility();
+        assert_eq!(pos.legal_moves().len() as u32, ours);
+        let flipped = pos.with_side_to_move(Color::White);
+        let (moves, drops) = flipped.mobility(Color::White);
+        assert_eq!(moves + drops + 4, theirs);
+
+        // 後手の指し手がすべて合法なら合法手の数と一致する
+        let pos = Position::new(
+            PartialPosition::from_usi(
+                "sfen lnsgkg1nl/1r5s1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/7R1/LNSGKGSNL b Bb 1",
+            )
+            .expect("failed to parse"),
+        );
+        let (moves, drops) = pos.mobility(Color::Black);
+        let (their_moves, their_drops) = pos.mobility(Color::White);
+        assert_eq!(
+            (moves + drops, their_moves + their_drops),
+            pos.both_mobility()
+        );
+
+        // 後手の2八歩打ちは打ち歩詰めなので数えない
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 9/9/9/9/9/9/7g1/6p2/6PKL b 2p 1")
+                .expect("failed to parse"),
+        );
+        let flipped = pos.with_side_flipped();
+        assert!(!flipped.legal_moves().contains(&Move::Drop {
+            to: Square::SQ_2H,
+            piece: Piece::W_P,
+        }));
+        assert_eq!(flipped.legal_moves().len() as u32, pos.both_mobility().1);
+
+        // 別々に生成した数と一致する
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(186);
+        for sfen in [
+            "sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1",
+            "sfen 8l/1l+R2P3/p2pBG1pp/kps1p4/Nn1P2G2/P1P1P2PP/1PS6/1KSG3+r1/LN2+p3L w Sbgn3p 124",
+            "sfen R8/2K1S1SSk/4B4/9/9/9/9/9/1L1L1L3 b RBGSNLP3g3n17p 1",
+        ] {
+            let mut pos = Position::new(PartialPosition::from_usi(sfen).expect("failed to parse"));
+            for _ in 0..100 {
+                let mut counter = MoveCounter(0);
+                pos.with_side_flipped().generate(&mut counter);
+                assert_eq!(
+                    (pos.legal_moves().len() as u32, counter.0),
+                    pos.both_mobility(),
+                    "{}",
+                    pos.to_sfen()
+                );
+                let moves = pos.legal_moves();
+                if moves.is_empty() {
+                    break;
+                }
+                pos.do_move(moves[rng.gen_range(0..moves.len())]);
+            }
+        }
+    }
+
+    #[test]
+    fn capture_and_quiet_moves() {
+        let test_cases = [
+            // with drops
+            "sfen lnsgkg1nl/1r5s1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/7R1/LNSGKGSNL b Bb 1",
+            // with captures
+            "sfen lnsgkgsnl/1r5b1/ppppppp1p/7p1/7P1/9/PPPPPPP1P/1B5R1/LNSGKGSNL b - 1",
+            // in check
+            "sfen lnsgk1snl/1r4g2/p1pppp1pp/6p2/1p5P1/2P6/PP1PPPP1P/1B1b3R1/LNSGKGSNL b - 1",
+        ];
+        for sfen in test_cases {
+            let pos = Position::new(PartialPosition::from_usi(sfen).expect("failed to parse"));
+            let captures = pos.capture_moves();
+            let quiets = pos.quiet_moves(false);
+            let quiets_with_drops = pos.quiet_moves(true);
+            assert!(captures
+                .iter()
+                .all(|m| !m.is_drop() && pos.piece_at(m.to()).is_some()));
+            assert!(quiets
+                .iter()
+                .all(|m| !m.is_drop() && pos.piece_at(m.to()).is_none()));
+            let drops = pos
+                .legal_moves()
+                .into_iter()
+                .filter(|m| m.is_drop())
+                .collect::<Vec<_>>();
+            let mut expected = pos.legal_moves().to_vec();
+            expected.sort();
+            let mut union = captures.to_vec();
+            union.extend(quiets);
+            union.extend(drops);
+            union.sort();
+            assert_eq!(expected, union, "{sfen}");
+            let mut union = captures.to_vec();
+            union.extend(quiets_with_drops);
+            union.sort();
+            assert_eq!(expected, union, "{sfen}");
+        }
+    }
+
+    #[test]
+    fn promotion_moves_of() {
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 8k/3P5/9/5S3/9/9/9/9/4K4 b P 1")
+                .expect("failed to parse"),
+        );
+        let moves = pos.promotion_moves_of(PieceKind::Silver);
+        assert!(!moves.is_empty());
+        for m in &moves {
+            assert!(m.is_promoting());
+            assert_eq!(Some(Square::SQ_4D), m.from());
+        }
+        assert_eq!(
+            pos.promotion_moves().len(),
+            moves.len() + pos.promotion_moves_of(PieceKind::Pawn).len()
+        );
+        assert!(pos.promotion_moves_of(PieceKind::Gold).is_empty());
+        assert!(pos.promotion_moves_of(PieceKind::ProSilver).is_empty());
+    }
+
+    #[test]
+    fn promotion_moves() {
+        // P1 *  *  *  *  *  *  *  * -OU
+        // P2 *  *  * +FU *  *  *  *  *
+        // P3 *  *  *  *  *  *  *  *  *
+        // P4 *  *  *  *  * +GI *  *  *
+        // P5 *  *  *  *  *  *  *  *  *
+        // P6 *  *  *  *  *  *  *  *  *
+        // P7 *  *  *  *  *  *  *  *  *
+        // P8 *  *  *  *  *  *  *  *  *
+        // P9 *  *  *  * +OU *  *  *  *
+        // P+00FU
+        // +
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 8k/3P5/9/5S3/9/9/9/9/4K4 b P 1")
+                .expect("failed to parse"),
+        );
+        let moves = pos.promotion_moves();
+        assert!(moves.iter().all(|m| m.is_promoting()));
+        let mut expected = pos
+            .legal_moves()
+            .into_iter()
+            .filter(|m| m.is_promoting())
+            .collect::<Vec<_>>();
+        expected.sort();
+        let mut actual = moves.to_vec();
+        actual.sort();
+        assert_eq!(expected, actual);
+        // 歩の成りは強制
+        let to_last_rank = |promote| Move::Normal {
+            from: Square::SQ_6B,
+            to: Square::SQ_6A,
+            promote,
+        };
+        assert!(moves.contains(&to_last_rank(true)));
+        assert!(!pos.legal_moves().contains(&to_last_rank(false)));
+        // 銀の成りは任意
+        let silver = |promote| Move::Normal {
+            from: Square::SQ_4D,
+            to: Square::SQ_4C,
+            promote,
+        };
+        assert!(moves.contains(&silver(true)));
+        assert!(!moves.contains(&silver(false)));
+        assert!(pos.legal_moves().contains(&silver(false)));
+        assert_eq!(4, moves.len());
+    }
+
+    #[test]
+    fn drop_moves() {
+        // P1-KY-KE-GI-KI-OU-KI * -KE-KY
+        // P2 * -HI *  *  *  *  * -GI *
+        // P3-FU-FU-FU-FU-FU-FU * -FU-FU
+        // P4 *  *  *  *  *  * -FU *  *
+        // P5 *  *  *  *  *  *  *  *  *
+        // P6 *  * +FU *  *  *  *  *  *
+        // P7+FU+FU * +FU+FU+FU+FU+FU+FU
+        // P8 *  *  *  *  *  *  * +HI *
+        // P9+KY+KE+GI+GI+KI+OU+KI+GI+KE+KY
+        // P+00KA
+        // P-00KA
+        // +
+        let pos = Position::new(
+            PartialPosition::from_usi(
+                "sfen lnsgkg1nl/1r5s1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/7R1/LNSGKGSNL b Bb 1",
+            )
+            .expect("failed to parse"),
+        );
+        assert_eq!(
+            43,
+            pos.legal_moves()
+                .into_iter()
+                .filter(|m| m.is_drop())
+                .count()
+        );
+    }
+
+    #[test]
+    fn drop_order() {
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 4k4/9/9/9/9/9/9/9/4K4 b GP 1")
+                .expect("failed to parse"),
+        );
+        let drops = pos
+            .legal_moves()
+            .into_iter()
+            .skip_while(|m| !m.is_drop())
+            .collect::<Vec<_>>();
+        assert!(drops.iter().all(|m| m.is_drop()));
+        // 歩 (1段目を除く) の後に金、それぞれ升目の番号順
+        let mut expected = Vec::new();
+        for (piece, skip_first_rank) in [(Piece::B_P, true), (Piece::B_G, false)] {
+            for to in Square::all() {
+                if pos.piece_at(to).is_none() && !(skip_first_rank && to.rank() == 1) {
+                    expected.push(Move::Drop { to, piece });
+                }
+            }
+        }
+        assert_eq!(expected, drops);
+        assert_eq!(pos.legal_moves(), pos.filter_legal(&pos.legal_moves()));
+    }
+
+    #[test]
+    fn maximum_moves() {
+        // http://lfics81.techblog.jp/archives/2041940.html
+        // P1+HI *  *  *  *  *  *  *  *
+        // P2 *  * +OU * +GI * +GI+GI-OU
+        // P3 *  *  *  * +KA *  *  *  *
+        // P4 *  *  *  *  *  *  *  *  *
+        // P5 *  *  *  *  *  *  *  *  *
+        // P6 *  *  *  *  *  *  *  *  *
+        // P7 *  *  *  *  *  *  *  *  *
+        // P8 *  *  *  *  *  *  *  *  *
+        // P9 * +KY * +KY * +KY *  *  *
+        // P+00FU00KY00KE00GI00KI00KA00HI
+        // P-00AL
+        // +
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen R8/2K1S1SSk/4B4/9/9/9/9/9/1L1L1L3 b RBGSNLP3g3n17p 1")
+                .expect("failed to parse"),
+        );
+        assert_eq!(593, pos.legal_moves().len());
+    }
+
+    #[test]
+    fn promotion_ranks() {
+        // P1 *  *  *  * -OU *  *  *  *
+        // P2 *  *  *  *  *  *  *  *  *
+        // P3 *  *  *  *  *  *  *  *  *
+        // P4 *  *  *  * +GI *  *  *  *
+        // P5 *  *  *  *  *  *  *  *  *
+        // P6 *  *  *  *  *  *  *  *  *
+        // P7 *  *  *  *  *  *  *  *  *
+        // P8 *  *  *  *  *  *  *  *  *
+        // P9 *  *  *  * +OU *  *  *  *
+        // +
+        let partial = PartialPosition::from_usi("sfen 4k4/9/9/4S4/9/9/9/9/4K4 b - 1")
+            .expect("failed to parse");
+        for (promotion_ranks, expected) in [(2, 0), (3, 3), (4, 5)] {
+            let pos = Position::with_rules(
+                partial.clone(),
+                Rules {
+                    promotion_ranks,
+                    ..Default::default()
+                },
+            );
+            assert_eq!(
+                expected,
+                pos.legal_moves()
+                    .iter()
+                    .filter(|m| m.is_promoting())
+                    .count(),
+                "promotion_ranks: {promotion_ranks}"
+            );
+        }
+    }
+
+    #[test]
+    fn max_legal_moves() {
+        fn max_moves(pos: &mut Position, depth: usize) -> usize {
+            let moves = pos.legal_moves();
+            let mut ret = moves.len();
+            if depth > 1 {
+                for m in moves {
+                    pos.do_move(m);
+                    ret = ret.max(max_moves(pos, depth - 1));
+                    pos.undo_move(m);
+                }
+            }
+            ret
+        }
+
+        assert!(max_moves(&mut Position::default(), 3) <= MAX_LEGAL_MOVES);
+        let mut pos = Position::new(
+            PartialPosition::from_usi("sfen R8/2K1S1SSk/4B4/9/9/9/9/9/1L1L1L3 b RBGSNLP3g3n17p 1")
+                .expect("failed to parse"),
+        );
+        assert_eq!(MAX_LEGAL_MOVES, max_moves(&mut pos, 2));
+    }
 
     #[test]
     fn pawn_drop() {
@@ -533,6 +1551,625 @@ mod tests {
         }
     }
 
+    #[test]
+    fn generate_with_stats() {
+        let (moves, stats) = Position::default().generate_with_stats();
+        assert_eq!(30, moves.len());
+        assert_eq!(
+            GenStats {
+                board_moves: 30,
+                drops: 0,
+                promotions: 0,
+                rejected: 0,
+            },
+            stats
+        );
+        // pinned silver can't move
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 4k4/4r4/9/9/9/9/9/4S4/4K4 b P 1")
+                .expect("failed to parse"),
+        );
+        let (moves, stats) = pos.generate_with_stats();
+        assert_eq!(pos.legal_moves().len(), moves.len());
+        assert_eq!(
+            moves.len() as u32,
+            stats.board_moves + stats.drops - stats.rejected
+        );
+        assert_eq!(4, stats.rejected);
+        assert_eq!(69, stats.drops);
+    }
+
+    #[test]
+    fn is_check_evasion() {
+        // P1 *  *  *  * -OU *  *  *  *
+        // P2 *  *  *  *  *  *  *  *  *
+        // P3 *  *  *  *  *  *  *  *  *
+        // P4 *  *  *  * -HI *  *  *  *
+        // P5 *  *  *  *  *  *  *  *  *
+        // P6 *  *  *  *  *  *  *  *  *
+        // P7 *  *  *  *  *  * +KA *  *
+        // P8 *  *  *  *  *  *  *  *  *
+        // P9 *  *  * +GI+OU *  *  *  *
+        // P+00KI
+        // P-00AL
+        // +
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 4k4/9/9/4r4/9/9/6B2/9/3SK4 b Gr2b3g3s4n4l18p 1")
+                .expect("failed to parse"),
+        );
+        let evasions = [
+            // 玉が逃げる
+            Move::Normal {
+                from: Square::SQ_5I,
+                to: Square::SQ_4H,
+                promote: false,
+            },
+            // 王手している駒を取る
+            Move::Normal {
+                from: Square::SQ_3G,
+                to: Square::SQ_5E,
+                promote: false,
+            },
+            // 合駒
+            Move::Normal {
+                from: Square::SQ_6I,
+                to: Square::SQ_5H,
+                promote: false,
+            },
+            Move::Drop {
+                to: Square::SQ_5F,
+                piece: Piece::B_G,
+            },
+        ];
+        for m in evasions {
+            assert!(pos.is_check_evasion(m), "{m:?}");
+        }
+        let non_evasions = [
+            Move::Normal {
+                from: Square::SQ_5I,
+                to: Square::SQ_5H,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_6I,
+                to: Square::SQ_6H,
+                promote: false,
+            },
+            Move::Drop {
+                to: Square::SQ_1A,
+                piece: Piece::B_G,
+            },
+        ];
+        for m in non_evasions {
+            assert!(!pos.is_check_evasion(m), "{m:?}");
+        }
+        let evasion_count = pos
+            .legal_moves()
+            .iter()
+            .filter(|&&m| pos.is_check_evasion(m))
+            .count();
+        assert_eq!(pos.legal_moves().len(), evasion_count);
+        assert!(!Position::default().is_check_evasion(Move::Normal {
+            from: Square::SQ_7G,
+            to: Square::SQ_7F,
+            promote: false,
+        }));
+    }
+
+    #[test]
+    fn legal_move_counts_by_piece() {
+        let counts = Position::default().legal_move_counts_by_piece();
+        assert_eq!(30, counts.iter().sum::<usize>());
+        assert_eq!(9, counts[PieceKind::Pawn.array_index()]);
+        assert_eq!(2, counts[PieceKind::Lance.array_index()]);
+        assert_eq!(0, counts[PieceKind::Knight.array_index()]);
+        assert_eq!(4, counts[PieceKind::Silver.array_index()]);
+        assert_eq!(6, counts[PieceKind::Gold.array_index()]);
+        assert_eq!(0, counts[PieceKind::Bishop.array_index()]);
+        assert_eq!(6, counts[PieceKind::Rook.array_index()]);
+        assert_eq!(3, counts[PieceKind::King.array_index()]);
+
+        // 成り・不成の両方と打つ手
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 4k4/9/9/P8/9/9/9/9/4K4 b N 1")
+                .expect("failed to parse"),
+        );
+        let counts = pos.legal_move_counts_by_piece();
+        assert_eq!(2, counts[PieceKind::Pawn.array_index()]);
+        assert_eq!(
+            pos.legal_moves().iter().filter(|m| m.is_drop()).count(),
+            counts[PieceKind::Knight.array_index()]
+        );
+        assert_eq!(pos.legal_moves().len(), counts.iter().sum::<usize>());
+    }
+
+    #[test]
+    fn for_each_legal() {
+        for sfen in [
+            "sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1",
+            "sfen R8/2K1S1SSk/4B4/9/9/9/9/9/1L1L1L3 b RBGSNLP3g3n17p 1",
+            "sfen 4k4/9/9/4r4/9/9/6B2/9/3SK4 b Gr2b3g3s4n4l18p 1",
+        ] {
+            let pos = Position::new(PartialPosition::from_usi(sfen).expect("failed to parse"));
+            let mut moves = Vec::new();
+            pos.for_each_legal(|m| {
+                moves.push(m);
+                ControlFlow::Continue(())
+            });
+            let mut expected = pos.legal_moves().to_vec();
+            moves.sort_unstable();
+            expected.sort_unstable();
+            assert_eq!(expected, moves);
+        }
+        let mut count = 0;
+        Position::default().for_each_legal(|_| {
+            count += 1;
+            if count == 5 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!(5, count);
+    }
+
+    #[test]
+    fn filter_legal() {
+        let normal = |from, to, promote| Move::Normal { from, to, promote };
+        let pos = Position::default();
+        let moves = [
+            normal(Square::SQ_2G, Square::SQ_2F, false),
+            // 2マス進む歩
+            normal(Square::SQ_2G, Square::SQ_2E, false),
+            // 後手の駒
+            normal(Square::SQ_3C, Square::SQ_3D, false),
+            normal(Square::SQ_7G, Square::SQ_7F, false),
+            // 持ち駒がない
+            Move::Drop {
+                to: Square::SQ_5E,
+                piece: Piece::B_B,
+            },
+            normal(Square::SQ_5I, Square::SQ_5H, false),
+            normal(Square::SQ_7G, Square::SQ_7F, true),
+        ];
+        assert_eq!(
+            vec![moves[0], moves[3], moves[5]],
+            pos.filter_legal(&moves).to_vec()
+        );
+        assert!(pos.filter_legal(&[]).is_empty());
+
+        // 重複は最初の1つだけ残り、上限を超える入力でも panic しない
+        let moves = [moves[3]; MAX_LEGAL_MOVES + 10];
+        assert_eq!(vec![moves[0]], pos.filter_legal(&moves).to_vec());
+
+        // 角に釘付けにされた銀
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 4k4/9/9/9/8b/9/6S2/9/4K4 b - 1")
+                .expect("failed to parse"),
+        );
+        let moves = [
+            normal(Square::SQ_3G, Square::SQ_3F, false),
+            normal(Square::SQ_3G, Square::SQ_2F, false),
+            normal(Square::SQ_5I, Square::SQ_4H, false),
+            normal(Square::SQ_3G, Square::SQ_4H, false),
+        ];
+        assert_eq!(
+            vec![moves[1], moves[2], moves[3]],
+            pos.filter_legal(&moves).to_vec()
+        );
+    }
+
+    #[test]
+    fn legal_moves_usi() {
+        let moves = Position::default().legal_moves_usi();
+        assert_eq!(30, moves.len());
+        assert!(moves.contains(&String::from("7g7f")));
+        assert!(moves.contains(&String::from("2g2f")));
+
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 4k4/9/4P4/9/9/9/9/9/4K4 b G 1")
+                .expect("failed to parse"),
+        );
+        let moves = pos.legal_moves_usi();
+        assert!(moves.contains(&String::from("5c5b+")));
+        assert!(moves.contains(&String::from("5c5b")));
+        assert!(moves.contains(&String::from("G*5b")));
+    }
+
+    #[test]
+    fn legal_moves_from() {
+        let pos = Position::default();
+        assert_eq!(1, pos.legal_moves_from(Square::SQ_7G).len());
+        assert_eq!(6, pos.legal_moves_from(Square::SQ_2H).len());
+        assert!(pos.legal_moves_from(Square::SQ_5E).is_empty());
+        assert!(pos.legal_moves_from(Square::SQ_3C).is_empty());
+        for sq in Square::all() {
+            let mut expected = pos
+                .legal_moves()
+                .into_iter()
+                .filter(|m| m.from() == Some(sq))
+                .collect::<Vec<_>>();
+            let mut moves = pos.legal_moves_from(sq).to_vec();
+            expected.sort_unstable();
+            moves.sort_unstable();
+            assert_eq!(expected, moves);
+        }
+
+        // P1 *  *  *  * -OU *  *  *  *
+        // P2 *  *  *  * -HI *  *  *  *
+        // P3 *  *  *  *  *  *  *  *  *
+        // P4 *  *  *  *  *  *  *  *  *
+        // P5 *  *  *  *  *  *  *  * -KA
+        // P6 *  *  *  *  *  *  *  *  *
+        // P7 *  *  *  * +HI * +GI *  *
+        // P8 *  *  *  *  *  *  *  *  *
+        // P9 *  *  *  * +OU *  *  *  *
+        // P-00AL
+        // +
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 4k4/4r4/9/9/8b/9/4R1S2/9/4K4 b - 1")
+                .expect("failed to parse"),
+        );
+        // 飛車は筋に沿ってのみ動ける
+        let moves = pos.legal_moves_from(Square::SQ_5G);
+        assert_eq!(8, moves.len());
+        assert!(moves.iter().all(|m| m.to().file() == 5));
+        // 角に pin された銀は斜めにのみ動ける
+        let mut tos = pos
+            .legal_moves_from(Square::SQ_3G)
+            .iter()
+            .map(|m| m.to())
+            .collect::<Vec<_>>();
+        tos.sort();
+        assert_eq!(vec![Square::SQ_2F, Square::SQ_4H], tos);
+    }
+
+    #[test]
+    fn legal_moves_in_region() {
+        let region = |sqs: &[Square]| {
+            sqs.iter()
+                .fold(Bitboard::empty(), |bb, &sq| bb | Bitboard::single(sq))
+        };
+        let check = |pos: &Position, region: Bitboard| {
+            let mut expected = pos
+                .legal_moves()
+                .into_iter()
+                .filter(|m| {
+                    region.contains(m.to()) && m.from().is_none_or(|from| region.contains(from))
+                })
+                .collect::<Vec<_>>();
+            let mut actual = pos.legal_moves_in_region(region).to_vec();
+            expected.sort();
+            actual.sort();
+            assert_eq!(expected, actual);
+            actual
+        };
+        // 右下の3x3
+        let corner = Square::all()
+            .filter(|sq| sq.file() <= 3 && sq.rank() >= 7)
+            .fold(Bitboard::empty(), |bb, sq| bb | Bitboard::single(sq));
+        let moves = check(&Position::default(), corner);
+        assert!(moves
+            .iter()
+            .all(|m| m.to().file() <= 3 && m.to().rank() >= 7));
+        // 2筋の歩は領域の外に出るので含まない
+        assert!(!moves.contains(&Move::Normal {
+            from: Square::SQ_2G,
+            to: Square::SQ_2F,
+            promote: false,
+        }));
+        assert!(moves.contains(&Move::Normal {
+            from: Square::SQ_2H,
+            to: Square::SQ_3H,
+            promote: false,
+        }));
+
+        // 持ち駒と王手
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 4k4/9/9/9/9/9/4r4/9/4K4 b G 1")
+                .expect("failed to parse"),
+        );
+        assert!(pos.in_check());
+        let moves = check(&pos, region(&[Square::SQ_5H, Square::SQ_5I, Square::SQ_4H]));
+        let mut tos = moves.iter().map(|m| m.to()).collect::<Vec<_>>();
+        tos.sort();
+        assert_eq!(vec![Square::SQ_4H, Square::SQ_5H], tos);
+        check(&pos, corner);
+        assert!(pos.legal_moves_in_region(Bitboard::empty()).is_empty());
+    }
+
+    #[test]
+    fn checking_moves() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(161);
+        for sfen in [
+            "sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1",
+            "sfen 8l/1l+R2P3/p2pBG1pp/kps1p4/Nn1P2G2/P1P1P2PP/1PS6/1KSG3+r1/LN2+p3L w Sbgn3p 124",
+            "sfen R8/2K1S1SSk/4B4/9/9/9/9/9/1L1L1L3 b RBGSNLP3g3n17p 1",
+            // 銀が飛車の利きを遮っていて、成って動くと開き王手
+            "sfen 4k4/9/9/4S4/9/9/9/9/K3R4 b - 1",
+            // 王手されている
+            "sfen 3k5/4r4/9/9/9/9/9/3S5/4K4 b RBGSNLP 1",
+        ] {
+            let mut pos = Position::new(PartialPosition::from_usi(sfen).expect("failed to parse"));
+            for _ in 0..100 {
+                let mut expected = pos
+                    .legal_moves()
+                    .into_iter()
+                    .filter(|&m| pos.is_check_move(m))
+                    .collect::<Vec<_>>();
+                let mut actual = pos.checking_moves().to_vec();
+                expected.sort();
+                actual.sort();
+                assert_eq!(expected, actual, "{}", pos.to_sfen());
+
+                let moves = pos.legal_moves();
+                if moves.is_empty() {
+                    break;
+                }
+                pos.do_move(moves[rng.gen_range(0..moves.len())]);
+            }
+        }
+        // 銀が筋から外れる手はすべて開き王手、筋に残る手は王手にならない
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 4k4/9/9/4S4/9/9/9/9/K3R4 b - 1")
+                .expect("failed to parse"),
+        );
+        let mut tos = pos
+            .checking_moves()
+            .iter()
+            .filter(|m| m.from() == Some(Square::SQ_5D))
+            .map(|m| (m.to(), m.is_promoting()))
+            .collect::<Vec<_>>();
+        tos.sort();
+        assert_eq!(
+            vec![
+                (Square::SQ_4C, false),
+                (Square::SQ_4C, true),
+                (Square::SQ_4E, false),
+                (Square::SQ_6C, false),
+                (Square::SQ_6C, true),
+                (Square::SQ_6E, false),
+            ],
+            tos
+        );
+        // 金打ちの王手
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 4k4/9/9/9/9/9/9/9/4K4 b G 1").expect("failed to parse"),
+        );
+        let mut tos = pos
+            .checking_moves()
+            .iter()
+            .map(|m| m.to())
+            .collect::<Vec<_>>();
+        tos.sort();
+        assert_eq!(
+            vec![
+                Square::SQ_4A,
+                Square::SQ_4B,
+                Square::SQ_5B,
+                Square::SQ_6A,
+                Square::SQ_6B
+            ],
+            tos
+        );
+    }
+
+    #[test]
+    fn ambiguous_movers() {
+        let pos = Position::default();
+        // 5八には2枚の金が動ける
+        assert_eq!(
+            Bitboard::single(Square::SQ_4I) | Bitboard::single(Square::SQ_6I),
+            pos.ambiguous_movers(PieceKind::Gold, Square::SQ_5H)
+        );
+        assert_eq!(
+            Bitboard::single(Square::SQ_6I),
+            pos.ambiguous_movers(PieceKind::Gold, Square::SQ_7H)
+        );
+        // 手番でない側の駒は含まない
+        assert!(pos
+            .ambiguous_movers(PieceKind::Gold, Square::SQ_5B)
+            .is_empty());
+        // pin された金は動けない
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 4k4/9/9/9/4r4/9/9/3GG4/4K4 b - 1")
+                .expect("failed to parse"),
+        );
+        assert_eq!(
+            Bitboard::single(Square::SQ_6H),
+            pos.ambiguous_movers(PieceKind::Gold, Square::SQ_6G)
+        );
+        assert_eq!(
+            Bitboard::single(Square::SQ_5H) | Bitboard::single(Square::SQ_6H),
+            pos.ambiguous_movers(PieceKind::Gold, Square::SQ_5G)
+        );
+    }
+
+    #[test]
+    fn checking_drops() {
+        // 飛車打ちの王手は玉と同じ段と筋のみ、歩より先は遮られる
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 4k4/9/4p4/9/9/9/9/9/4K4 b R 1")
+                .expect("failed to parse"),
+        );
+        let mut tos = pos
+            .checking_drops()
+            .iter()
+            .map(|m| m.to())
+            .collect::<Vec<_>>();
+        tos.sort();
+        let mut expected = vec![
+            Square::SQ_1A,
+            Square::SQ_2A,
+            Square::SQ_3A,
+            Square::SQ_4A,
+            Square::SQ_6A,
+            Square::SQ_7A,
+            Square::SQ_8A,
+            Square::SQ_9A,
+            Square::SQ_5B,
+        ];
+        expected.sort();
+        assert_eq!(expected, tos);
+
+        for sfen in [
+            "sfen 8l/1l+R2P3/p2pBG1pp/kps1p4/Nn1P2G2/P1P1P2PP/1PS6/1KSG3+r1/LN2+p3L w Sbgn3p 124",
+            "sfen R8/2K1S1SSk/4B4/9/9/9/9/9/1L1L1L3 b RBGSNLP3g3n17p 1",
+            // 打ち歩詰め
+            "sfen 9/7pp/8k/7P1/7G1/9/9/9/9 b P2r2b3g4s4n4l14p 1",
+            // 王手されていて、合駒の桂打ちが王手になる
+            "sfen 3k5/4r4/9/9/9/9/9/9/4K4 b RBGSNLP 1",
+        ] {
+            let pos = Position::new(PartialPosition::from_usi(sfen).expect("failed to parse"));
+            let mut expected = pos
+                .legal_moves()
+                .into_iter()
+                .filter(|&m| m.is_drop() && pos.is_check_move(m))
+                .collect::<Vec<_>>();
+            let mut actual = pos.checking_drops().to_vec();
+            expected.sort();
+            actual.sort();
+            assert_eq!(expected, actual, "{sfen}");
+        }
+    }
+
+    #[test]
+    fn legal_moves_avoiding_repetition() {
+        let normal = |from, to| Move::Normal {
+            from,
+            to,
+            promote: false,
+        };
+        // +5958OU,-5152OU,+5859OU,-5251OU を繰り返す
+        let moves = [
+            normal(Square::SQ_5I, Square::SQ_5H),
+            normal(Square::SQ_5A, Square::SQ_5B),
+            normal(Square::SQ_5H, Square::SQ_5I),
+            normal(Square::SQ_5B, Square::SQ_5A),
+        ];
+        let mut pos = Position::default();
+        for &m in moves.iter().cycle().take(11) {
+            assert_eq!(pos.legal_moves(), pos.legal_moves_avoiding_repetition());
+            pos.do_move(m);
+        }
+        // -5251OU で初期局面が4回目になる
+        let legal_moves = pos.legal_moves();
+        let avoiding = pos.legal_moves_avoiding_repetition();
+        assert!(legal_moves.contains(&moves[3]));
+        assert!(!avoiding.contains(&moves[3]));
+        assert_eq!(legal_moves.len() - 1, avoiding.len());
+    }
+
+    #[test]
+    fn king_moves() {
+        // P1 *  *  *  *  *  *  *  *  *
+        // P2 *  *  *  *  *  *  *  *  *
+        // P3 *  *  *  *  *  *  *  *  *
+        // P4 *  *  *  * -KI *  *  *  *
+        // P5 *  *  *  *  *  *  *  *  *
+        // P6 *  *  *  *  *  *  *  *  *
+        // P7 *  *  * +FU+FU *  *  *  *
+        // P8-HI *  *  * +OU *  *  *  *
+        // P9 *  *  *  *  * +KI *  *  *
+        // P-00AL
+        // +
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 9/9/9/4g4/9/9/3PP4/r3K4/5G3 b - 1")
+                .expect("failed to parse"),
+        );
+        // 王手されている段の横には逃げられない
+        let mut tos = pos.king_moves().iter().map(|m| m.to()).collect::<Vec<_>>();
+        tos.sort();
+        let mut expected = vec![Square::SQ_4G, Square::SQ_5I, Square::SQ_6I];
+        expected.sort();
+        assert_eq!(expected, tos);
+        for m in pos.king_moves() {
+            assert!(pos.legal_moves().contains(&m));
+        }
+        assert_eq!(
+            pos.legal_moves()
+                .iter()
+                .filter(|m| m.from() == Some(Square::SQ_5H))
+                .count(),
+            pos.king_moves().len()
+        );
+    }
+
+    #[test]
+    fn can_enter_king() {
+        let position =
+            |sfen| Position::new(PartialPosition::from_usi(sfen).expect("failed to parse"));
+        // 初期局面でも玉は一段上がれる
+        assert!(Position::default().can_enter_king(Color::Black));
+        assert!(Position::default().can_enter_king(Color::White));
+        // 4段目の玉は敵陣に入れる
+        let pos = position("sfen 4k4/9/9/4K4/9/9/9/9/9 b - 1");
+        assert!(pos.can_enter_king(Color::Black));
+        // 3段目には全て後手の金が利いている
+        let pos = position("sfen 4k4/4g4/9/4K4/9/9/9/9/9 b - 1");
+        assert!(!pos.can_enter_king(Color::Black));
+        // 5段目の玉は敵陣の一段手前に上がれる
+        let pos = position("sfen 4k4/9/9/9/4K4/9/9/9/9 b - 1");
+        assert!(pos.can_enter_king(Color::Black));
+        // 4段目には全て後手の金が利いている
+        let pos = position("sfen 4k4/9/4g4/9/4K4/9/9/9/9 b - 1");
+        assert!(!pos.can_enter_king(Color::Black));
+        // 手番に関わらず後手玉について調べられる
+        let pos = position("sfen 9/9/9/9/9/4k4/9/7PP/7NK b - 1");
+        assert!(pos.can_enter_king(Color::White));
+        assert!(!pos.can_enter_king(Color::Black));
+    }
+
+    #[test]
+    fn legal_moves_match_full_verification() {
+        fn fully_verified(pos: &mut Position) -> Vec<Move> {
+            let c = pos.side_to_move();
+            let mut av = MoveList::new();
+            pos.generate(&mut av);
+            let mut ret = Vec::new();
+            for m in av {
+                pos.do_move(m);
+                let safe = pos.king_position(c).is_none_or(|sq| {
+                    pos.attackers_to(c.flip(), sq, &pos.occupied_bitboard())
+                        .is_empty()
+                });
+                pos.undo_move(m);
+                if safe {
+                    ret.push(m);
+                }
+            }
+            ret.sort_unstable();
+            ret
+        }
+        fn perft(pos: &mut Position, depth: usize) -> u64 {
+            let mut moves = pos.legal_moves().to_vec();
+            moves.sort_unstable();
+            assert_eq!(fully_verified(pos), moves);
+            if depth == 0 {
+                return 1;
+            }
+            let mut count = 0;
+            for m in moves {
+                pos.do_move(m);
+                count += perft(pos, depth - 1);
+                pos.undo_move(m);
+            }
+            count
+        }
+
+        let mut pos = Position::default();
+        assert_eq!(25470, perft(&mut pos, 3));
+        // pinned pieces on both sides
+        let mut pos = Position::new(
+            PartialPosition::from_usi("sfen 4k4/4r4/9/4G4/9/2b6/9/4S4/4K4 b - 1")
+                .expect("failed to parse"),
+        );
+        perft(&mut pos, 3);
+    }
+
     #[allow(clippy::bool_assert_comparison)]
     #[test]
     fn is_pawn_drop_mate() {
@@ -671,4 +2308,119 @@ mod tests {
             assert_eq!(expected, pos.is_pawn_drop_mate(sq), "failed at {i}");
         }
     }
+
+    #[test]
+    fn drop_rejection() {
+        let position =
+            |sfen| Position::new(PartialPosition::from_usi(sfen).expect("failed to parse"));
+        let pos = position("sfen 4k4/9/9/9/9/9/4P4/9/4K4 b NP 1");
+        for (pk, to, expected) in [
+            (PieceKind::Pawn, Square::SQ_4E, None),
+            (PieceKind::Knight, Square::SQ_4C, None),
+            (
+                PieceKind::Gold,
+                Square::SQ_4E,
+                Some(DropRejection::NotInHand),
+            ),
+            (
+                PieceKind::King,
+                Square::SQ_4E,
+                Some(DropRejection::NotInHand),
+            ),
+            (
+                PieceKind::Pawn,
+                Square::SQ_5G,
+                Some(DropRejection::Occupied),
+            ),
+            (
+                PieceKind::Pawn,
+                Square::SQ_4A,
+                Some(DropRejection::LastRank),
+            ),
+            (
+                PieceKind::Knight,
+                Square::SQ_4B,
+                Some(DropRejection::LastRank),
+            ),
+            (PieceKind::Pawn, Square::SQ_5E, Some(DropRejection::Nifu)),
+        ] {
+            assert_eq!(expected, pos.drop_rejection(pk, to), "{pk:?} {to:?}");
+        }
+        // 王手を防がない駒打ち
+        let pos = position("sfen 4k4/9/9/9/4r4/9/9/9/4K4 b G 1");
+        assert_eq!(
+            Some(DropRejection::InCheck),
+            pos.drop_rejection(PieceKind::Gold, Square::SQ_4H)
+        );
+        assert_eq!(None, pos.drop_rejection(PieceKind::Gold, Square::SQ_5H));
+        // 打ち歩詰め
+        let pos = position("sfen 9/7pp/8k/7P1/7G1/9/9/9/9 b P2r2b3g4s4n4l14p 1");
+        assert_eq!(
+            Some(DropRejection::Uchifuzume),
+            pos.drop_rejection(PieceKind::Pawn, Square::SQ_1D)
+        );
+
+        // 合法手の駒打ちと一致する
+        for sfen in [
+            "sfen 8l/1l+R2P3/p2pBG1pp/kps1p4/Nn1P2G2/P1P1P2PP/1PS6/1KSG3+r1/LN2+p3L w Sbgn3p 124",
+            "sfen R8/2K1S1SSk/4B4/9/9/9/9/9/1L1L1L3 b RBGSNLP3g3n17p 1",
+            "sfen 9/7pp/8k/7P1/7G1/9/9/9/9 b P2r2b3g4s4n4l14p 1",
+            "sfen 3k5/4r4/9/9/9/9/9/9/4K4 b RBGSNLP 1",
+        ] {
+            let pos = position(sfen);
+            let legal_moves = pos.legal_moves();
+            let piece = |pk| Piece::new(pk, pos.side_to_move());
+            for pk in DROP_ORDER {
+                for to in Square::all() {
+                    assert_eq!(
+                        legal_moves.contains(&Move::Drop {
+                            to,
+                            piece: piece(pk)
+                        }),
+                        pos.drop_rejection(pk, to).is_none(),
+                        "{sfen} {pk:?} {to:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pawn_push_mate() {
+        // 歩を突いて詰ますのは打ち歩詰めではない
+        // P1 *  *  *  *  *  *  *  *  *
+        // P2 *  *  *  *  *  *  * -FU-FU
+        // P3 *  *  *  *  *  *  *  * -OU
+        // P4 *  *  *  *  *  *  * +FU *
+        // P5 *  *  *  *  *  *  * +KI+FU
+        // P6 *  *  *  *  *  *  *  *  *
+        // P7 *  *  *  *  *  *  *  *  *
+        // P8 *  *  *  *  *  *  *  *  *
+        // P9 *  *  *  *  *  *  *  *  *
+        // P-00AL
+        // +
+        let mut pos = Position::new(
+            PartialPosition::from_usi("sfen 9/7pp/8k/7P1/7GP/9/9/9/9 b 2r2b3g4s4n4l14p 1")
+                .expect("failed to parse"),
+        );
+        let m = Move::Normal {
+            from: Square::SQ_1E,
+            to: Square::SQ_1D,
+            promote: false,
+        };
+        assert!(pos.legal_moves().contains(&m));
+        pos.do_move(m);
+        assert!(pos.in_check());
+        assert!(pos.legal_moves().is_empty());
+
+        // 同じ地点への歩打ちは打ち歩詰めで除外される
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 9/7pp/8k/7P1/7G1/9/9/9/9 b P2r2b3g4s4n4l14p 1")
+                .expect("failed to parse"),
+        );
+        assert!(!pos.legal_moves().contains(&Move::Drop {
+            to: Square::SQ_1D,
+            piece: Piece::B_P,
+        }));
+    }
 }
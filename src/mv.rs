@@ -0,0 +1,321 @@
+use crate::Position;
+use shogi_core::{Hand, Move, Piece, PieceKind, Square};
+use std::fmt;
+
+/// An error that can occur in [`Position::play_usi`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayError {
+    /// The string is not a move in USI format.
+    InvalidUsi,
+    /// The move is not legal in the position.
+    IllegalMove,
+}
+
+impl fmt::Display for PlayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayError::InvalidUsi => f.write_str("invalid USI move"),
+            PlayError::IllegalMove => f.write_str("illegal move"),
+        }
+    }
+}
+
+impl std::error::Error for PlayError {}
+
+fn parse_square(s: &[u8]) -> Option<Square> {
+    match *s {
+        [file @ b'1'..=b'9', rank @ b'a'..=b'i'] => Square::new(file - b'0', rank - b'a' + 1),
+        _ => None,
+    }
+}
+
+fn square_to_western(sq: Square) -> String {
+    format!("{}{}", sq.file(), (b'a' + sq.rank() - 1) as char)
+}
+
+fn piece_kind_to_western(pk: PieceKind) -> &'static str {
+    match pk {
+        PieceKind::Pawn => "P",
+        PieceKind::Lance => "L",
+        PieceKind::Knight => "N",
+        PieceKind::Silver => "S",
+        PieceKind::Gold => "G",
+        PieceKind::Bishop => "B",
+        PieceKind::Rook => "R",
+        PieceKind::King => "K",
+        PieceKind::ProPawn => "+P",
+        PieceKind::ProLance => "+L",
+        PieceKind::ProKnight => "+N",
+        PieceKind::ProSilver => "+S",
+        PieceKind::ProBishop => "+B",
+        PieceKind::ProRook => "+R",
+    }
+}
+
+/// Extension methods for [`Move`].
+pub trait MoveExt {
+    /// Formats the move in the Western (Hodges) notation, e.g. `"P-7f"`, `"Bx2b+"` or `"P*5e"`.
+    ///
+    /// The source square is written only if another piece of the same kind can move to the
+    /// destination, and `=` marks a declined promotion. `self` must be legal in `pos`.
+    fn to_western(&self, pos: &Position) -> String;
+}
+
+impl MoveExt for Move {
+    fn to_western(&self, pos: &Position) -> String {
+        match *self {
+            Move::Normal { from, to, promote } => {
+                let pk = pos
+                    .piece_at(from)
+                    .expect("no piece on the source square")
+                    .piece_kind();
+                let legal = pos.legal_moves();
+                let mut s = String::from(piece_kind_to_western(pk));
+                if pos.movers_in(&legal, pk, to).count() > 1 {
+                    s.push_str(&square_to_western(from));
+                }
+                s.push(if pos.piece_at(to).is_some() { 'x' } else { '-' });
+                s.push_str(&square_to_western(to));
+                if promote {
+                    s.push('+');
+                } else if legal.contains(&Move::Normal {
+                    from,
+                    to,
+                    promote: true,
+                }) {
+                    s.push('=');
+                }
+                s
+            }
+            Move::Drop { to, piece } => format!(
+                "{}*{}",
+                piece_kind_to_western(piece.piece_kind()),
+                square_to_western(to)
+            ),
+        }
+    }
+}
+
+/// A move whose moving piece is left to be resolved from a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartialMove {
+    Normal {
+        from: Square,
+        to: Square,
+        promote: bool,
+    },
+    Drop {
+        to: Square,
+        piece_kind: PieceKind,
+    },
+}
+
+impl PartialMove {
+    pub fn normal(from: Square, to: Square, promote: bool) -> Self {
+        PartialMove::Normal { from, to, promote }
+    }
+    pub fn drop(piece_kind: PieceKind, to: Square) -> Self {
+        PartialMove::Drop { to, piece_kind }
+    }
+    /// Parses a move in USI format, e.g. `"7g7f"`, `"8h2b+"` or `"B*5e"`.
+    pub fn from_usi(s: &str) -> Option<Self> {
+        match s.as_bytes() {
+            [pk, b'*', to @ ..] => {
+                let piece_kind = match pk {
+                    b'P' => PieceKind::Pawn,
+                    b'L' => PieceKind::Lance,
+                    b'N' => PieceKind::Knight,
+                    b'S' => PieceKind::Silver,
+                    b'G' => PieceKind::Gold,
+                    b'B' => PieceKind::Bishop,
+                    b'R' => PieceKind::Rook,
+                    _ => return None,
+                };
+                Some(PartialMove::drop(piece_kind, parse_square(to)?))
+            }
+            [from @ .., b'+'] if from.len() == 4 => Some(PartialMove::normal(
+                parse_square(&from[..2])?,
+                parse_square(&from[2..])?,
+                true,
+            )),
+            [from0, from1, to0, to1] => Some(PartialMove::normal(
+                parse_square(&[*from0, *from1])?,
+                parse_square(&[*to0, *to1])?,
+                false,
+            )),
+            _ => None,
+        }
+    }
+}
+
+impl Position {
+    /// Resolves `pm` into a [`Move`] of the side to move.
+    ///
+    /// Returns `None` if there is no piece of the side to move on `from`,
+    /// or if the piece kind to drop can't be in hand. The legality of the move is not checked.
+    pub fn resolve(&self, pm: PartialMove) -> Option<Move> {
+        let c = self.side_to_move();
+        match pm {
+            PartialMove::Normal { from, to, promote } => {
+                if self.piece_at(from)?.color() != c {
+                    return None;
+                }
+                Some(Move::Normal { from, to, promote })
+            }
+            PartialMove::Drop { to, piece_kind } => {
+                if !Hand::is_hand_piece(piece_kind) {
+                    return None;
+                }
+                Some(Move::Drop {
+                    to,
+                    piece: Piece::new(piece_kind, c),
+                })
+            }
+        }
+    }
+    /// Parses `usi` as a move of the side to move and applies it if legal.
+    ///
+    /// On error, the position is left unchanged.
+    pub fn play_usi(&mut self, usi: &str) -> Result<Move, PlayError> {
+        let pm = PartialMove::from_usi(usi).ok_or(PlayError::InvalidUsi)?;
+        let m = self
+            .resolve(pm)
+            .filter(|m| self.legal_moves().contains(m))
+            .ok_or(PlayError::IllegalMove)?;
+        self.do_move(m);
+        Ok(m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve() {
+        let mut pos = Position::default();
+        assert_eq!(
+            Some(Move::Normal {
+                from: Square::SQ_7G,
+                to: Square::SQ_7F,
+                promote: false,
+            }),
+            pos.resolve(PartialMove::normal(Square::SQ_7G, Square::SQ_7F, false))
+        );
+        // empty square
+        assert_eq!(
+            None,
+            pos.resolve(PartialMove::normal(Square::SQ_5E, Square::SQ_5D, false))
+        );
+        // opponent's piece
+        assert_eq!(
+            None,
+            pos.resolve(PartialMove::normal(Square::SQ_3C, Square::SQ_3D, false))
+        );
+        assert_eq!(
+            None,
+            pos.resolve(PartialMove::drop(PieceKind::King, Square::SQ_5E))
+        );
+        pos.do_move(Move::Normal {
+            from: Square::SQ_7G,
+            to: Square::SQ_7F,
+            promote: false,
+        });
+        assert_eq!(
+            Some(Move::Drop {
+                to: Square::SQ_5E,
+                piece: Piece::W_B,
+            }),
+            pos.resolve(PartialMove::drop(PieceKind::Bishop, Square::SQ_5E))
+        );
+    }
+
+    #[test]
+    fn from_usi() {
+        assert_eq!(
+            Some(PartialMove::normal(Square::SQ_7G, Square::SQ_7F, false)),
+            PartialMove::from_usi("7g7f")
+        );
+        assert_eq!(
+            Some(PartialMove::normal(Square::SQ_8H, Square::SQ_2B, true)),
+            PartialMove::from_usi("8h2b+")
+        );
+        assert_eq!(
+            Some(PartialMove::drop(PieceKind::Bishop, Square::SQ_5E)),
+            PartialMove::from_usi("B*5e")
+        );
+        for s in [
+            "", "7g7", "7g7f7", "0a1a", "7j7f", "K*5e", "b*5e", "7g7f-", "resign",
+        ] {
+            assert_eq!(None, PartialMove::from_usi(s), "{s}");
+        }
+    }
+
+    #[test]
+    fn play_usi() {
+        let mut pos = Position::default();
+        assert_eq!(
+            Ok(Move::Normal {
+                from: Square::SQ_7G,
+                to: Square::SQ_7F,
+                promote: false,
+            }),
+            pos.play_usi("7g7f")
+        );
+        let key = pos.key();
+        let ply = pos.ply();
+        // 後手番で先手の駒を動かす
+        assert_eq!(Err(PlayError::IllegalMove), pos.play_usi("2g2f"));
+        // 飛車の利きを越える
+        assert_eq!(Err(PlayError::IllegalMove), pos.play_usi("8b8h"));
+        assert_eq!(Err(PlayError::IllegalMove), pos.play_usi("B*5e"));
+        assert_eq!(Err(PlayError::InvalidUsi), pos.play_usi("3c3"));
+        assert_eq!(key, pos.key());
+        assert_eq!(ply, pos.ply());
+        assert!(pos.play_usi("3c3d").is_ok());
+        assert!(pos.play_usi("8h2b+").is_ok());
+        assert_eq!(Some(Piece::B_PB), pos.piece_at(Square::SQ_2B));
+    }
+
+    #[test]
+    fn to_western() {
+        let mut pos = Position::default();
+        let mut notations = Vec::new();
+        for usi in ["7g7f", "3c3d", "8h2b+", "3a2b", "B*4e", "6a5b"] {
+            let pm = PartialMove::from_usi(usi).expect("invalid USI");
+            let m = pos.resolve(pm).expect("failed to resolve");
+            notations.push(m.to_western(&pos));
+            pos.do_move(m);
+        }
+        assert_eq!(
+            vec!["P-7f", "P-3d", "Bx2b+", "Sx2b", "B*4e", "G6a-5b"],
+            notations
+        );
+
+        // 同じ地点に動ける金が2枚ある
+        let pos = Position::default();
+        let m = pos
+            .resolve(PartialMove::normal(Square::SQ_6I, Square::SQ_5H, false))
+            .unwrap();
+        assert_eq!("G6i-5h", m.to_western(&pos));
+        let m = pos
+            .resolve(PartialMove::normal(Square::SQ_5I, Square::SQ_5H, false))
+            .unwrap();
+        assert_eq!("K-5h", m.to_western(&pos));
+
+        // 成れるのに成らない手と成駒の移動
+        let pos = Position::from_sfen("4k4/9/9/2P6/9/9/9/+R8/4K4 b - 1").expect("failed to parse");
+        let m = pos
+            .resolve(PartialMove::normal(Square::SQ_7D, Square::SQ_7C, false))
+            .unwrap();
+        assert_eq!("P-7c=", m.to_western(&pos));
+        let m = pos
+            .resolve(PartialMove::normal(Square::SQ_7D, Square::SQ_7C, true))
+            .unwrap();
+        assert_eq!("P-7c+", m.to_western(&pos));
+        let m = pos
+            .resolve(PartialMove::normal(Square::SQ_9H, Square::SQ_9A, false))
+            .unwrap();
+        assert_eq!("+R-9a", m.to_western(&pos));
+    }
+}
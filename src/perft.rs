@@ -0,0 +1,127 @@
+use crate::Position;
+
+/// Counts the leaf nodes of the legal move tree of depth `depth`.
+pub fn perft(pos: &mut Position, depth: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    if depth == 1 {
+        return pos.legal_moves().len() as u64;
+    }
+    let mut count = 0;
+    for m in pos.legal_moves() {
+        pos.do_move(m);
+        count += perft(pos, depth - 1);
+        pos.undo_move(m);
+    }
+    count
+}
+
+/// Same as [`perft`], memoizing subtree counts in `tt`.
+///
+/// Subtrees are identified by [`Position::key`] and the remaining depth. Two different
+/// positions sharing the same 64-bit key would be mixed up, which is negligible in practice.
+pub fn perft_tt(pos: &mut Position, depth: usize, tt: &mut PerftTable) -> u64 {
+    if depth <= 1 {
+        return perft(pos, depth);
+    }
+    let key = pos.key();
+    if let Some(count) = tt.probe(key, depth) {
+        return count;
+    }
+    let mut count = 0;
+    for m in pos.legal_moves() {
+        pos.do_move(m);
+        count += perft_tt(pos, depth - 1, tt);
+        pos.undo_move(m);
+    }
+    tt.store(key, depth, count);
+    count
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Entry {
+    key: u64,
+    // 0 means an empty slot
+    depth: u32,
+    count: u64,
+}
+
+/// Transposition table for [`perft_tt`].
+///
+/// Each key maps to a single slot, and a newer entry always replaces the older one stored in
+/// the same slot. Lookups compare the full key and the depth, so a replaced entry is merely a
+/// miss and never yields a wrong count.
+#[derive(Debug, Clone)]
+pub struct PerftTable {
+    entries: Vec<Entry>,
+}
+
+impl PerftTable {
+    /// Creates a table with at least `len` entries, rounded up to a power of two.
+    pub fn new(len: usize) -> Self {
+        Self {
+            entries: vec![Entry::default(); len.max(1).next_power_of_two()],
+        }
+    }
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    /// Always returns `false`: a table has at least one entry.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    /// Removes all entries.
+    pub fn clear(&mut self) {
+        self.entries.fill(Entry::default());
+    }
+    fn index(&self, key: u64) -> usize {
+        key as usize & (self.entries.len() - 1)
+    }
+    fn probe(&self, key: u64, depth: usize) -> Option<u64> {
+        let entry = &self.entries[self.index(key)];
+        (entry.key == key && entry.depth as usize == depth).then_some(entry.count)
+    }
+    fn store(&mut self, key: u64, depth: usize, count: u64) {
+        let index = self.index(key);
+        self.entries[index] = Entry {
+            key,
+            depth: depth as u32,
+            count,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shogi_core::PartialPosition;
+    use shogi_usi_parser::FromUsi;
+
+    #[test]
+    fn perft_tt() {
+        let mut pos = Position::default();
+        let mut tt = PerftTable::new(1 << 16);
+        for (depth, expected) in [1, 30, 900, 25470, 719731].into_iter().enumerate() {
+            assert_eq!(expected, super::perft_tt(&mut pos, depth, &mut tt));
+        }
+        // 置換が頻繁に起こる小さな表でも結果は変わらない
+        let mut pos = Position::new(
+            PartialPosition::from_usi(
+                "sfen l6nl/5+P1gk/2np1S3/p1p4Pp/3P2Sp1/1PPb2P1P/P5GS1/R8/LN4bKL w RGgsn5p 1",
+            )
+            .expect("failed to parse"),
+        );
+        let mut tt = PerftTable::new(16);
+        assert_eq!(16, tt.len());
+        for depth in 0..=3 {
+            assert_eq!(
+                perft(&mut pos, depth),
+                super::perft_tt(&mut pos, depth, &mut tt)
+            );
+        }
+        tt.clear();
+        assert_eq!(perft(&mut pos, 3), super::perft_tt(&mut pos, 3, &mut tt));
+    }
+}
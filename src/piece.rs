@@ -0,0 +1,123 @@
+use shogi_core::{Color, Piece, PieceKind, Square};
+
+/// Piece kinds in the order of the widely used engine numbering (as in YaneuraOu).
+const USI_ORDER: [PieceKind; PieceKind::NUM] = [
+    PieceKind::Pawn,
+    PieceKind::Lance,
+    PieceKind::Knight,
+    PieceKind::Silver,
+    PieceKind::Bishop,
+    PieceKind::Rook,
+    PieceKind::Gold,
+    PieceKind::King,
+    PieceKind::ProPawn,
+    PieceKind::ProLance,
+    PieceKind::ProKnight,
+    PieceKind::ProSilver,
+    PieceKind::ProBishop,
+    PieceKind::ProRook,
+];
+
+/// Offset of White's pieces in the numbering.
+const WHITE_OFFSET: u8 = 16;
+
+/// Extension methods for [`Piece`].
+pub trait PieceExt: Sized {
+    /// Returns the piece of `index` in the engine numbering used by YaneuraOu and others:
+    /// Black's P, L, N, S, B, R, G, K are 1 to 8, +P, +L, +N, +S, +B, +R are 9 to 14,
+    /// and White's pieces are the same numbers plus 16 (17 to 30). 0 is no piece.
+    fn from_usi_index(index: u8) -> Option<Self>;
+    /// Returns the index of the piece in the engine numbering. See [`PieceExt::from_usi_index`].
+    fn to_usi_index(self) -> u8;
+    /// Returns `true` if the piece would have no further move on `sq` (行き所のない駒),
+    /// i.e. it must promote when moving there.
+    fn must_promote(self, sq: Square) -> bool;
+    /// Returns `true` if the piece placed on `sq` has somewhere to move on an empty board.
+    ///
+    /// This is `false` exactly for the pieces which [`PieceExt::must_promote`] there,
+    /// e.g. a pawn on the last rank, which can't be placed or dropped on `sq`.
+    fn has_legal_destination(self, sq: Square) -> bool;
+}
+
+impl PieceExt for Piece {
+    fn from_usi_index(index: u8) -> Option<Self> {
+        let (c, i) = match index {
+            1..=14 => (Color::Black, index),
+            17..=30 => (Color::White, index - WHITE_OFFSET),
+            _ => return None,
+        };
+        Some(Piece::new(USI_ORDER[i as usize - 1], c))
+    }
+    fn to_usi_index(self) -> u8 {
+        let (pk, c) = self.to_parts();
+        let i = USI_ORDER.iter().position(|&k| k == pk).unwrap() as u8 + 1;
+        match c {
+            Color::Black => i,
+            Color::White => i + WHITE_OFFSET,
+        }
+    }
+    fn must_promote(self, sq: Square) -> bool {
+        let (pk, c) = self.to_parts();
+        match pk {
+            PieceKind::Pawn | PieceKind::Lance => sq.relative_rank(c) == 1,
+            PieceKind::Knight => sq.relative_rank(c) <= 2,
+            _ => false,
+        }
+    }
+    #[inline(always)]
+    fn has_legal_destination(self, sq: Square) -> bool {
+        !self.must_promote(sq)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usi_index() {
+        assert_eq!(1, Piece::B_P.to_usi_index());
+        assert_eq!(7, Piece::B_G.to_usi_index());
+        assert_eq!(8, Piece::B_K.to_usi_index());
+        assert_eq!(14, Piece::B_PR.to_usi_index());
+        assert_eq!(21, Piece::W_B.to_usi_index());
+        assert_eq!(Some(Piece::W_PB), Piece::from_usi_index(29));
+        for index in [0, 15, 16, 31, 255] {
+            assert_eq!(None, Piece::from_usi_index(index));
+        }
+        for c in Color::all() {
+            for pk in PieceKind::all() {
+                let p = Piece::new(pk, c);
+                assert_eq!(Some(p), Piece::from_usi_index(p.to_usi_index()));
+            }
+        }
+    }
+
+    #[test]
+    fn must_promote() {
+        assert!(Piece::B_P.must_promote(Square::SQ_5A));
+        assert!(!Piece::B_P.must_promote(Square::SQ_5B));
+        assert!(Piece::W_L.must_promote(Square::SQ_1I));
+        assert!(Piece::B_N.must_promote(Square::SQ_3B));
+        assert!(!Piece::B_N.must_promote(Square::SQ_3C));
+        assert!(Piece::W_N.must_promote(Square::SQ_3H));
+        assert!(!Piece::B_S.must_promote(Square::SQ_5A));
+        assert!(!Piece::B_PP.must_promote(Square::SQ_5A));
+    }
+
+    #[test]
+    fn has_legal_destination() {
+        // 最終段の歩と香、最後の2段の桂は動けない
+        assert!(!Piece::B_P.has_legal_destination(Square::SQ_5A));
+        assert!(!Piece::W_P.has_legal_destination(Square::SQ_5I));
+        assert!(!Piece::B_L.has_legal_destination(Square::SQ_1A));
+        assert!(!Piece::B_N.has_legal_destination(Square::SQ_2B));
+        assert!(Piece::B_P.has_legal_destination(Square::SQ_5B));
+        assert!(Piece::W_P.has_legal_destination(Square::SQ_5A));
+        assert!(Piece::B_N.has_legal_destination(Square::SQ_2C));
+        assert!(Piece::B_PP.has_legal_destination(Square::SQ_5A));
+        for sq in Square::all() {
+            assert!(Piece::B_G.has_legal_destination(sq));
+        }
+    }
+}
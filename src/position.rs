@@ -1,52 +1,113 @@
 use crate::bitboard::Bitboard;
-use crate::tables::{ATTACK_TABLE, BETWEEN_TABLE};
+use crate::hand::{HandExt, HAND_PIECE_NUMS};
+use crate::piece::PieceExt;
+use crate::rules::Rules;
+use crate::square::SquareExt;
+use crate::tables::{ATTACK_TABLE, BETWEEN_TABLE, PROMOTABLE};
+use crate::values::PieceValues;
 use crate::zobrist::{Key, ZOBRIST_TABLE};
 use shogi_core::{Color, Hand, Move, Piece, PieceKind, Square};
 
 /// Represents a state of the game.
+///
+/// The precomputed tables are global, so cloning copies only the board, hands and the history
+/// of the moves played, whose cost grows with the number of moves.
+/// Use [`Position::clone_shallow`] to clone without the history.
 #[derive(Debug, Clone)]
 pub struct Position {
     inner: PartialPosition,
     states: Vec<State>,
+    captures: Vec<Piece>,
+    moves: Vec<Move>,
+    rules: Rules,
+    track_history: bool,
+    captured_stack: Vec<Option<Piece>>, // 履歴を記録しないときに戻すための取った駒
+    base_ply: u32,                      // states の最初の局面の手数
 }
 
 impl Position {
     pub fn new(partial: shogi_core::PartialPosition) -> Position {
+        Self::with_rules(partial, Rules::default())
+    }
+    /// Creates a position played under the given `rules`.
+    ///
+    /// Panics if `rules.promotion_ranks` is greater than 9.
+    pub fn with_rules(partial: shogi_core::PartialPosition, rules: Rules) -> Position {
+        assert!(rules.promotion_ranks <= 9, "invalid promotion ranks");
+        let base_ply = u32::from(partial.ply());
         let inner = PartialPosition::from(partial);
-        let mut keys = (Key::ZERO, Key::ZERO);
-        for sq in Square::all() {
-            if let Some(p) = inner.board[sq.array_index()] {
-                keys.0 ^= ZOBRIST_TABLE.board(sq, p);
-            }
-        }
-        for c in Color::all() {
-            for pk in Hand::all_hand_pieces() {
-                if let Some(num) = inner.hands[c.array_index()].count(pk) {
-                    for i in 0..num {
-                        keys.1 ^= ZOBRIST_TABLE.hand(c, pk, i);
-                    }
-                }
-            }
-        }
-        let checkers = AttackInfo::calculate_checkers(&inner);
-        let state = State {
-            keys,
-            captured: None,
-            last_moved: None,
-            attack_info: AttackInfo::new(checkers, &inner),
-        };
+        let state = State::initial(&inner);
         Self {
             inner,
             states: vec![state],
+            captures: Vec::new(),
+            moves: Vec::new(),
+            rules,
+            track_history: true,
+            captured_stack: Vec::new(),
+            base_ply,
         }
     }
     #[inline(always)]
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+    #[inline(always)]
     pub fn side_to_move(&self) -> Color {
         self.inner.side
     }
+    /// Returns the side to move.
+    #[inline(always)]
+    pub fn us(&self) -> Color {
+        self.inner.side
+    }
+    /// Returns the opponent of the side to move.
+    #[inline(always)]
+    pub fn them(&self) -> Color {
+        self.inner.side.flip()
+    }
+    /// Returns the rank of `sq` from the side to move's perspective, in `0..9`.
+    ///
+    /// 0 is always the back rank of the side to move and 8 is that of the opponent.
+    #[inline(always)]
+    pub fn relative_rank(&self, sq: Square) -> u8 {
+        match self.inner.side {
+            Color::Black => 9 - sq.rank(),
+            Color::White => sq.rank() - 1,
+        }
+    }
+    /// The largest ply. [`Position::ply`] saturates at it instead of wrapping around,
+    /// and moves can still be applied and undone there.
+    pub const MAX_PLY: u32 = u32::MAX;
+
+    #[inline(always)]
+    pub fn ply(&self) -> u32 {
+        self.base_ply.saturating_add(self.moves_played() as u32)
+    }
+    /// Sets the ply, keeping the history.
+    ///
+    /// Panics if the history would start before ply 1, i.e. `ply` is less than
+    /// the number of positions in the history (1 without moves played).
+    pub fn set_ply(&mut self, ply: u32) {
+        let len = self.moves_played() + 1;
+        assert!(
+            ply as usize >= len,
+            "ply {ply} is too small for the history of {len} positions"
+        );
+        self.base_ply = ply - (len - 1) as u32;
+    }
+    /// Returns the number of moves played since `base_ply`, which can still be undone.
     #[inline(always)]
-    pub fn ply(&self) -> u16 {
-        self.inner.ply
+    fn moves_played(&self) -> usize {
+        // 履歴を記録しないときは states は常に現局面のみ
+        self.states.len() - 1 + self.captured_stack.len()
+    }
+    /// Returns the index in the history of the position of `ply`,
+    /// or `None` if it's not within the history.
+    #[inline(always)]
+    fn history_index(&self, ply: u32) -> Option<usize> {
+        let index = ply.checked_sub(self.base_ply)? as usize;
+        (index < self.states.len()).then_some(index)
     }
     #[inline(always)]
     pub fn hand(&self, color: Color) -> Hand {
@@ -56,6 +117,20 @@ impl Position {
     pub fn piece_at(&self, sq: Square) -> Option<Piece> {
         self.inner.piece_at(sq)
     }
+    /// Returns the board indexed by [`Square::array_index`], i.e. 1a, 1b, ..., 1i, 2a, ..., 9i.
+    #[inline(always)]
+    pub fn board_array(&self) -> &[Option<Piece>; Square::NUM] {
+        &self.inner.board
+    }
+    /// Returns `true` if `self` and `other` have the same board, hands and side to move.
+    ///
+    /// Unlike comparing SFEN strings, the ply is ignored, so transpositions reached with a
+    /// different number of moves compare equal.
+    pub fn sfen_eq_ignoring_ply(&self, other: &Position) -> bool {
+        self.inner.side == other.inner.side
+            && self.inner.hands == other.inner.hands
+            && self.inner.board == other.inner.board
+    }
     #[inline(always)]
     pub fn key(&self) -> u64 {
         (self.state().keys.0 ^ self.state().keys.1).value()
@@ -64,10 +139,308 @@ impl Position {
     pub fn keys(&self) -> (u64, u64) {
         (self.state().keys.0.value(), self.state().keys.1.value())
     }
+    /// Returns the key of the position seen from the side to move.
+    ///
+    /// For Black this equals [`Position::key`]. For White, the key is calculated as if the board
+    /// were rotated by 180 degrees, the colors of all pieces and hands swapped and Black were to
+    /// move, so a position and its color-mirrored one share the same relative key.
+    pub fn relative_key(&self) -> u64 {
+        let us = self.side_to_move();
+        if us == Color::Black {
+            return self.key();
+        }
+        let mut key = Key::ZERO;
+        for sq in Square::all() {
+            if let Some(p) = self.piece_at(sq) {
+                let p = Piece::new(p.piece_kind(), p.color().flip());
+                key ^= ZOBRIST_TABLE.board(sq.relative(us), p);
+            }
+        }
+        for c in Color::all() {
+            let hand = self.hand(c);
+            for pk in Hand::all_hand_pieces() {
+                for i in 0..hand.count(pk).unwrap_or(0) {
+                    key ^= ZOBRIST_TABLE.hand(c.flip(), pk, i);
+                }
+            }
+        }
+        key.value()
+    }
+    #[inline(always)]
+    pub fn last_move(&self) -> Option<Move> {
+        self.state().last_move
+    }
+    /// Returns a hash of the whole game: the 64-bit FNV-1a hash of the board and hand keys
+    /// of all positions from the initial one, in order.
+    ///
+    /// Games passing through the same positions in the same order hash equal, so different move
+    /// orders reaching the same position hash differently. Distinct games may still collide,
+    /// so compare the games themselves when equality matters. The keys come from the zobrist
+    /// table generated with a fixed seed, so the value is stable across processes and builds
+    /// and can be stored to deduplicate a database of games.
+    /// Only the current position is hashed if the history is not tracked.
+    pub fn game_hash(&self) -> u64 {
+        self.states
+            .iter()
+            .flat_map(|state| [state.keys.0.value(), state.keys.1.value()])
+            .flat_map(u64::to_le_bytes)
+            .fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+                (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+    /// Returns the pieces captured so far in the game, oldest first.
+    ///
+    /// Always empty if the history is not tracked. See [`PositionBuilder::track_history`](crate::PositionBuilder::track_history).
+    #[inline(always)]
+    pub fn captures_history(&self) -> &[Piece] {
+        &self.captures
+    }
+    /// Returns the moves played since the position of `ply`, oldest first.
+    ///
+    /// Returns `None` if `ply` is beyond the current ply or before the start of the history,
+    /// or if the history is not tracked.
+    pub fn moves_since(&self, ply: u32) -> Option<&[Move]> {
+        if !self.track_history {
+            return None;
+        }
+        let index = self.history_index(ply)?;
+        Some(&self.moves[index..])
+    }
+    /// Returns the SFEN of each position in the history, oldest first and ending with
+    /// [`Position::to_sfen`] of the current one.
+    ///
+    /// The positions are reconstructed by undoing the moves on a copy of `self`.
+    /// Only the current position is returned if the history is not tracked.
+    pub fn history_sfens(&self) -> Vec<String> {
+        let mut pos = self.clone();
+        let mut sfens = vec![pos.to_sfen()];
+        for &m in self.moves.iter().rev() {
+            pos.undo_move(m);
+            sfens.push(pos.to_sfen());
+        }
+        sfens.reverse();
+        sfens
+    }
+    /// Returns the number of times the current position (board, hands and side to move)
+    /// has occurred in the history, including now. 1 means the first occurrence.
+    ///
+    /// Returns `None` if the history is not tracked.
+    pub fn repetition_count(&self) -> Option<usize> {
+        if !self.track_history {
+            return None;
+        }
+        let keys = self.state().keys;
+        Some(
+            self.states
+                .iter()
+                .filter(|state| state.keys == keys)
+                .count(),
+        )
+    }
+    /// Returns `true` if the current position has occurred four times (千日手),
+    /// so that a draw by repetition can be claimed now.
+    ///
+    /// Always `false` if the history is not tracked.
+    pub fn can_claim_repetition_draw(&self) -> bool {
+        self.repetition_count().is_some_and(|count| count >= 4)
+    }
+    /// Returns the mask of files (bit `file - 1`) which have an unpromoted pawn of `c`.
+    #[inline(always)]
+    pub fn pawn_files(&self, c: Color) -> u16 {
+        self.inner.pawn_files[c.array_index()]
+    }
+    /// Returns, for each piece kind, the squares from which a piece of the kind would give check
+    /// to the king of `c`, considering the current blockers. The entry of the king is empty.
+    pub fn check_squares(&self, c: Color) -> [Bitboard; PieceKind::NUM] {
+        if c == self.side_to_move() {
+            AttackInfo::calculate_checkables(&self.inner, c)
+        } else {
+            self.state().attack_info.checkables
+        }
+    }
+    /// Returns `true` if the king of `c` is in the promotion zone of `c` (入玉).
+    pub fn king_in_enemy_camp(&self, c: Color) -> bool {
+        self.king_position(c)
+            .is_some_and(|sq| self.promotable(sq, c))
+    }
+    /// Returns `true` if the king is the only piece of `c` on the board.
+    ///
+    /// Pieces in hand are not taken into account.
+    pub fn is_lone_king(&self, c: Color) -> bool {
+        self.player_bitboard(c).count() == 1 && self.king_position(c).is_some()
+    }
+    /// Returns `true` if the try rule is enabled and the side that just moved
+    /// has its king on the target square of [`Rules::try_rule_square`].
+    pub fn try_rule_won(&self) -> bool {
+        let c = self.side_to_move().flip();
+        let Some(sq) = self.rules.try_rule_square else {
+            return false;
+        };
+        let target = match c {
+            Color::Black => sq,
+            Color::White => sq.flip(),
+        };
+        self.king_position(c) == Some(target)
+    }
+    /// Returns the squares which a piece of `pk` and `c` would attack if placed on `sq`,
+    /// under the current occupancy.
+    #[inline(always)]
+    pub fn attacks_if_dropped(&self, pk: PieceKind, c: Color, sq: Square) -> Bitboard {
+        ATTACK_TABLE.attack(pk, sq, c, &self.occupied_bitboard())
+    }
+    /// Returns the squares attacked by the piece on `sq` under the current occupancy.
+    ///
+    /// Returns an empty bitboard if `sq` is empty.
+    pub fn attacks_of(&self, sq: Square) -> Bitboard {
+        self.piece_at(sq).map_or(Bitboard::empty(), |p| {
+            ATTACK_TABLE.attack(p.piece_kind(), sq, p.color(), &self.occupied_bitboard())
+        })
+    }
+    /// Returns the squares attacked by the pieces of `c`.
+    ///
+    /// With the `attack-maps` feature, the maps are updated incrementally by
+    /// [`Position::do_move`] and [`Position::undo_move`], so this is O(1) at the cost of slower
+    /// moves. Otherwise the map is calculated from the pieces of `c` on each call.
+    #[inline(always)]
+    pub fn attack_map(&self, c: Color) -> Bitboard {
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "attack-maps")] {
+                self.inner.attack_maps[c.array_index()]
+            } else {
+                self.calculate_attack_map(c)
+            }
+        }
+    }
+    /// Returns the number of pieces of `c` attacking `sq`.
+    #[cfg(feature = "attack-maps")]
+    #[inline(always)]
+    pub fn attack_count(&self, c: Color, sq: Square) -> u8 {
+        self.inner.attack_counts[c.array_index()][sq.array_index()]
+    }
+    fn calculate_attack_map(&self, c: Color) -> Bitboard {
+        let occ = self.occupied_bitboard();
+        let mut bb = Bitboard::empty();
+        for sq in self.player_bitboard(c) {
+            if let Some(p) = self.piece_at(sq) {
+                bb |= ATTACK_TABLE.attack(p.piece_kind(), sq, c, &occ);
+            }
+        }
+        bb
+    }
+    /// Returns `true` if the piece on `sq` is defended by another piece of its owner.
+    ///
+    /// Returns `false` if `sq` is empty.
+    pub fn is_defended(&self, sq: Square) -> bool {
+        self.piece_at(sq).is_some_and(|p| {
+            !self
+                .attackers_to(p.color(), sq, &self.occupied_bitboard())
+                .is_empty()
+        })
+    }
+    /// Returns the squares of the opponent's pieces attacking the king zone of `c`,
+    /// i.e. the king square and its neighbours.
+    ///
+    /// Returns an empty bitboard if `c` has no king.
+    pub fn attackers_in_king_zone(&self, c: Color) -> Bitboard {
+        let Some(king) = self.king_position(c) else {
+            return Bitboard::empty();
+        };
+        let occ = self.occupied_bitboard();
+        let zone = ATTACK_TABLE.attack(PieceKind::King, king, c, &occ) | Bitboard::single(king);
+        let mut bb = Bitboard::empty();
+        for sq in self.player_bitboard(c.flip()) {
+            if let Some(p) = self.piece_at(sq) {
+                if !(ATTACK_TABLE.attack(p.piece_kind(), sq, c.flip(), &occ) & zone).is_empty() {
+                    bb |= Bitboard::single(sq);
+                }
+            }
+        }
+        bb
+    }
+    /// Returns the number of the opponent's pieces attacking the king zone of `c`.
+    pub fn king_attacker_count(&self, c: Color) -> u32 {
+        self.attackers_in_king_zone(c).count() as u32
+    }
+    /// Returns the sum of `weights` of the opponent's pieces attacking the king zone of `c`.
+    pub fn king_attack_value(&self, c: Color, weights: &PieceValues) -> i32 {
+        self.attackers_in_king_zone(c)
+            .into_iter()
+            .filter_map(|sq| self.piece_at(sq))
+            .map(|p| weights.value(p.piece_kind()))
+            .sum()
+    }
+    /// Returns the number of files around the king of `c` (its file and the adjacent ones)
+    /// without an unpromoted pawn of `c`.
+    ///
+    /// Returns 0 if `c` has no king.
+    pub fn king_open_files(&self, c: Color) -> u32 {
+        let Some(king) = self.king_position(c) else {
+            return 0;
+        };
+        let file = king.file();
+        (file.max(2) - 1..=file.min(8) + 1)
+            .filter(|&f| self.pawn_files(c) & (1 << (f - 1)) == 0)
+            .count() as u32
+    }
+    /// Returns the number of empty squares adjacent to the king of `c`.
+    ///
+    /// Returns 0 if `c` has no king.
+    pub fn king_adjacent_empty(&self, c: Color) -> u32 {
+        let Some(king) = self.king_position(c) else {
+            return 0;
+        };
+        (ATTACK_TABLE.ou.attack(king, c) & !self.occupied_bitboard()).count() as u32
+    }
+    /// Returns the game phase in `0..=256`, where 256 means the full non-pawn material.
+    ///
+    /// The phase is `256 * m / M` rounded down, where `m` is the sum of [`PieceValues::DEFAULT`]
+    /// of the unpromoted kinds of the lances, knights, silvers, golds, bishops and rooks
+    /// on the board and in the hands, and `M` is that of the starting position.
+    /// Captured pieces go to the hand, so it is lower only with pieces removed from the game,
+    /// e.g. in handicap games and tsume problems.
+    pub fn phase(&self) -> u32 {
+        let value = |pk: PieceKind| match pk {
+            PieceKind::Pawn | PieceKind::King => 0,
+            _ => PieceValues::DEFAULT.value(pk.unpromote().unwrap_or(pk)),
+        };
+        let full = 4
+            * (value(PieceKind::Lance)
+                + value(PieceKind::Knight)
+                + value(PieceKind::Silver)
+                + value(PieceKind::Gold))
+            + 2 * (value(PieceKind::Bishop) + value(PieceKind::Rook));
+        let on_board = self
+            .occupied_bitboard()
+            .into_iter()
+            .filter_map(|sq| self.piece_at(sq))
+            .map(|p| value(p.piece_kind()))
+            .sum::<i32>();
+        let in_hands = Color::all()
+            .into_iter()
+            .flat_map(|c| self.hand(c).pieces())
+            .map(|(pk, n)| value(pk) * i32::from(n))
+            .sum::<i32>();
+        (256 * (on_board + in_hands) / full).min(256) as u32
+    }
     #[inline(always)]
     pub fn in_check(&self) -> bool {
         !self.checkers().is_empty()
     }
+    /// Returns the empty squares between the king of the side to move and the checking piece,
+    /// where a piece can be interposed.
+    ///
+    /// Returns an empty bitboard if not in check, in double check,
+    /// or if the checking piece is adjacent or a knight.
+    pub fn check_interposition_squares(&self) -> Bitboard {
+        let checkers = self.checkers();
+        match (self.king_position(self.side_to_move()), checkers.count()) {
+            (Some(king), 1) => {
+                let ch = checkers.into_iter().next().unwrap();
+                BETWEEN_TABLE[ch.array_index()][king.array_index()]
+            }
+            _ => Bitboard::empty(),
+        }
+    }
     pub fn is_check_move(&self, m: Move) -> bool {
         match m {
             Move::Normal { from, to, promote } => {
@@ -97,12 +470,87 @@ impl Position {
             Move::Drop { to, piece } => self.checkable(piece.piece_kind(), to),
         }
     }
+    /// Returns `true` if `m` captures a piece on the destination of the last move.
+    pub fn is_recapture(&self, m: Move) -> bool {
+        match self.last_move() {
+            Some(last) => last.to() == m.to() && self.piece_at(m.to()).is_some(),
+            None => false,
+        }
+    }
+    /// Returns `true` if `m` moves a piece to a square where it must promote,
+    /// so that the non-promoting variant of `m` is illegal.
+    ///
+    /// Returns `false` for drops and for moves from an empty square.
+    pub fn must_promote(&self, m: Move) -> bool {
+        match m {
+            Move::Normal { from, to, .. } => {
+                self.piece_at(from).is_some_and(|p| p.must_promote(to))
+            }
+            Move::Drop { .. } => false,
+        }
+    }
+    /// Returns the square and the piece, as it is on the board, which `m` would capture.
+    ///
+    /// Returns `None` for drops and for moves to an empty square.
+    pub fn capture_info(&self, m: Move) -> Option<(Square, Piece)> {
+        match m {
+            Move::Normal { to, .. } => self.piece_at(to).map(|p| (to, p)),
+            Move::Drop { .. } => None,
+        }
+    }
+    /// Returns [`Position::key`] as if the piece on `sq` were removed from the board.
+    ///
+    /// Returns the key itself if `sq` is empty.
+    pub fn key_without(&self, sq: Square) -> u64 {
+        match self.piece_at(sq) {
+            Some(p) => self.key() ^ ZOBRIST_TABLE.board(sq, p).value(),
+            None => self.key(),
+        }
+    }
+    /// Returns the XOR delta that `do_move(m)` applies to [`Position::key`].
+    ///
+    /// `self.key() ^ self.key_delta(m)` equals the key after the move.
+    pub fn key_delta(&self, m: Move) -> u64 {
+        let (board, hand) = self.keys_delta(m);
+        (board ^ hand).value()
+    }
+    /// Returns [`Position::key`] after applying `moves` in turn, without modifying `self`.
+    ///
+    /// `moves` must be legal in turn. The last move is applied with [`Position::key_delta`];
+    /// the earlier ones change the board the later deltas depend on, so they are played
+    /// on a [`Position::clone_shallow`] copy. Two move orders transpose if they give the same key.
+    pub fn keys_after(&self, moves: &[Move]) -> u64 {
+        match moves.split_last() {
+            None => self.key(),
+            Some((&last, [])) => self.key() ^ self.key_delta(last),
+            Some((&last, init)) => {
+                let mut pos = self.clone_shallow();
+                for &m in init {
+                    pos.do_move(m);
+                }
+                pos.key() ^ pos.key_delta(last)
+            }
+        }
+    }
+    /// Returns the legal moves paired with [`Position::key`] after each of them,
+    /// computed with [`Position::key_delta`] without applying the moves.
+    pub fn children(&self) -> Vec<(Move, u64)> {
+        let key = self.key();
+        self.legal_moves()
+            .into_iter()
+            .map(|m| (m, key ^ self.key_delta(m)))
+            .collect()
+    }
+    /// Applies `m`.
     pub fn do_move(&mut self, m: Move) {
         let c = self.side_to_move();
         let is_check = self.is_check_move(m);
         let captured = self.inner.piece_at(m.to());
         let last_moved;
         let mut keys = self.state().keys;
+        let delta = self.keys_delta(m);
+        keys.0 ^= delta.0;
+        keys.1 ^= delta.1;
         let checkers = match m {
             Move::Normal { from, to, promote } => {
                 let piece = self.inner.piece_at(from).unwrap();
@@ -114,17 +562,15 @@ impl Position {
                     } else {
                         pk
                     };
-                    // Update keys
-                    keys.0 ^= ZOBRIST_TABLE.board(to, p);
-                    keys.1 ^= ZOBRIST_TABLE.hand(
-                        c,
-                        pk_unpromoted,
-                        self.inner.hand_of_a_player(c).count(pk_unpromoted).unwrap(),
-                    );
                     // Update inner state
-                    self.inner.xor_piece(to, p);
-                    let hand = self.inner.hand_of_a_player_mut(c);
-                    *hand = hand.added(pk_unpromoted).unwrap();
+                    self.inner.remove_piece(to, p);
+                    self.inner
+                        .hand_of_a_player_mut(c)
+                        .add(pk_unpromoted, 1)
+                        .expect("hand overflow");
+                    if self.track_history {
+                        self.captures.push(p);
+                    }
                 }
                 let target_piece = if promote {
                     piece.promote().unwrap()
@@ -132,14 +578,9 @@ impl Position {
                     piece
                 };
                 // Update inner state
-                self.inner.xor_piece(from, piece);
-                self.inner.xor_piece(to, target_piece);
-                *self.inner.piece_at_mut(from) = None;
-                *self.inner.piece_at_mut(to) = Some(target_piece);
+                self.inner.remove_piece(from, piece);
+                self.inner.put_piece(to, target_piece);
                 self.inner.side = c.flip();
-                // Update keys
-                keys.0 ^= ZOBRIST_TABLE.board(from, piece);
-                keys.0 ^= ZOBRIST_TABLE.board(to, target_piece);
                 if is_check {
                     AttackInfo::calculate_checkers(&self.inner)
                 } else {
@@ -149,21 +590,12 @@ impl Position {
             Move::Drop { to, piece } => {
                 last_moved = Some(piece);
                 // Update inner state
-                self.inner.xor_piece(to, piece);
-                *self.inner.piece_at_mut(to) = Some(piece);
-                let hand = self.inner.hand_of_a_player_mut(c);
-                *hand = hand.removed(piece.piece_kind()).unwrap();
+                self.inner.put_piece(to, piece);
+                self.inner
+                    .hand_of_a_player_mut(c)
+                    .remove(piece.piece_kind(), 1)
+                    .expect("no piece to drop in hand");
                 self.inner.side = c.flip();
-                // Update keys
-                keys.1 ^= ZOBRIST_TABLE.hand(
-                    c,
-                    piece.piece_kind(),
-                    self.inner
-                        .hand_of_a_player(c)
-                        .count(piece.piece_kind())
-                        .unwrap(),
-                );
-                keys.0 ^= ZOBRIST_TABLE.board(to, piece);
                 if is_check {
                     Bitboard::single(to)
                 } else {
@@ -171,25 +603,41 @@ impl Position {
                 }
             }
         };
-        self.inner.ply += 1;
-        keys.0 ^= Key::COLOR;
-        self.states.push(State {
+        let state = State {
             keys,
             captured,
             last_moved,
+            last_move: Some(m),
             attack_info: AttackInfo::new(checkers, &self.inner),
-        });
+        };
+        if self.track_history {
+            self.states.push(state);
+            self.moves.push(m);
+        } else {
+            // 現局面の状態を上書きし、戻すのに必要な取った駒だけを残す
+            *self.states.last_mut().expect("empty states") = state;
+            self.captured_stack.push(captured);
+        }
+        self.debug_assert_moved(m);
     }
+    /// Undoes `m`, which must be the last move applied.
+    ///
+    /// If the history is not tracked, the previous state is recalculated,
+    /// and [`Position::last_move`] returns `None` afterwards.
     pub fn undo_move(&mut self, m: Move) {
         let c = self.side_to_move();
         match m {
-            Move::Normal {
-                from,
-                to,
-                promote: _,
-            } => {
-                let last_moved = self.last_moved().unwrap();
-                let captured = self.captured();
+            Move::Normal { from, to, promote } => {
+                let (last_moved, captured) = if self.track_history {
+                    (self.last_moved().unwrap(), self.captured())
+                } else {
+                    let p = self.inner.piece_at(to).unwrap();
+                    let last_moved = if promote { p.unpromote().unwrap() } else { p };
+                    let captured = self.captured_stack.pop().expect("no move to undo");
+                    (last_moved, captured)
+                };
+                self.inner
+                    .remove_piece(to, self.inner.piece_at(to).unwrap());
                 if let Some(p_cap) = captured {
                     let pk = p_cap.piece_kind();
                     let pk_unpromoted = if let Some(pk) = pk.unpromote() {
@@ -197,25 +645,256 @@ impl Position {
                     } else {
                         pk
                     };
-                    self.inner.xor_piece(to, p_cap);
-                    let hand = self.inner.hand_of_a_player_mut(c.flip());
-                    *hand = hand.removed(pk_unpromoted).unwrap();
+                    self.inner.put_piece(to, p_cap);
+                    self.inner
+                        .hand_of_a_player_mut(c.flip())
+                        .remove(pk_unpromoted, 1)
+                        .expect("hand underflow");
+                    if self.track_history {
+                        self.captures.pop();
+                    }
                 }
-                self.inner.xor_piece(from, last_moved);
-                self.inner.xor_piece(to, self.inner.piece_at(to).unwrap());
-                *self.inner.piece_at_mut(from) = Some(last_moved);
-                *self.inner.piece_at_mut(to) = captured;
+                self.inner.put_piece(from, last_moved);
             }
             Move::Drop { to, piece } => {
-                self.inner.xor_piece(to, piece);
-                *self.inner.piece_at_mut(to) = None;
-                let hand = self.inner.hand_of_a_player_mut(c.flip());
-                *hand = hand.added(piece.piece_kind()).unwrap();
+                if !self.track_history {
+                    self.captured_stack.pop().expect("no move to undo");
+                }
+                self.inner.remove_piece(to, piece);
+                self.inner
+                    .hand_of_a_player_mut(c.flip())
+                    .add(piece.piece_kind(), 1)
+                    .expect("hand overflow");
             }
         }
         self.inner.side = c.flip();
-        self.inner.ply -= 1;
-        self.states.pop();
+        if self.track_history {
+            self.states.pop();
+            self.moves.pop();
+        } else {
+            let delta = self.keys_delta(m);
+            let mut keys = self.state().keys;
+            keys.0 ^= delta.0;
+            keys.1 ^= delta.1;
+            let checkers = AttackInfo::calculate_checkers(&self.inner);
+            *self.states.last_mut().expect("empty states") = State {
+                keys,
+                captured: None,
+                last_moved: None,
+                last_move: None,
+                attack_info: AttackInfo::new(checkers, &self.inner),
+            };
+        }
+        self.debug_assert_moved(m);
+    }
+    /// The cheap part of [`Position::debug_assert_sound`], checked after each move:
+    /// the occupancy bitboards against each other and against the board on the squares of `m`.
+    #[inline(always)]
+    fn debug_assert_moved(&self, m: Move) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        let inner = &self.inner;
+        let occ = self.occupied_bitboard();
+        let pieces = inner
+            .piece_bb
+            .iter()
+            .fold(Bitboard::empty(), |bb, &piece_bb| bb | piece_bb);
+        assert!(
+            (inner.player_bb[0] & inner.player_bb[1]).is_empty() && pieces == occ,
+            "occupancy mismatch"
+        );
+        for sq in [Some(m.to()), m.from()].into_iter().flatten() {
+            let consistent = match inner.piece_at(sq) {
+                Some(p) => self.piece_bitboard(p).contains(sq),
+                None => !occ.contains(sq),
+            };
+            assert!(consistent, "occupancy mismatch");
+        }
+    }
+    /// Checks the consistency of the internal state and panics if it's corrupted:
+    /// the bitboards and the pawn files against the board, the kings,
+    /// the incremental keys and attacks against a full recalculation, and the numbers of pieces in hand.
+    ///
+    /// After each move, only the occupancy around the move is checked, as the full check would
+    /// make the debug builds several times slower. This is a no-op unless `debug_assertions`
+    /// is enabled.
+    pub fn debug_assert_sound(&self) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        let inner = &self.inner;
+        let mut player_bb = [Bitboard::empty(); Color::NUM];
+        let mut piece_bb = [Bitboard::empty(); PieceKind::NUM];
+        let mut pawn_files = [0; Color::NUM];
+        for sq in Square::all() {
+            if let Some(p) = inner.piece_at(sq) {
+                let (pk, c) = p.to_parts();
+                player_bb[c.array_index()] |= Bitboard::single(sq);
+                piece_bb[pk.array_index()] |= Bitboard::single(sq);
+                if pk == PieceKind::Pawn {
+                    pawn_files[c.array_index()] |= 1 << (sq.file() - 1);
+                }
+            }
+        }
+        assert_eq!(player_bb, inner.player_bb, "player bitboard mismatch");
+        assert_eq!(piece_bb, inner.piece_bb, "piece bitboard mismatch");
+        assert_eq!(pawn_files, inner.pawn_files, "pawn files mismatch");
+        for c in Color::all() {
+            let kings =
+                inner.player_bb[c.array_index()] & inner.piece_bb[PieceKind::King.array_index()];
+            assert!(kings.count() <= 1, "more than one king of {c:?}");
+            for (pk, num) in Hand::all_hand_pieces().zip(HAND_PIECE_NUMS) {
+                let count = inner.hand_of_a_player(c).count(pk).unwrap_or_default();
+                assert!(count <= num, "too many {pk:?} in the hand of {c:?}");
+            }
+        }
+        assert_eq!(inner.keys(), self.state().keys, "key mismatch");
+        #[cfg(feature = "attack-maps")]
+        {
+            let (counts, _) = inner.calculate_attacks();
+            assert_eq!(counts, inner.attack_counts, "attack count mismatch");
+            for c in Color::all() {
+                assert_eq!(
+                    self.calculate_attack_map(c),
+                    self.attack_map(c),
+                    "attack map mismatch"
+                );
+            }
+        }
+    }
+    /// Sets whether the history is tracked. The position must have no moves played.
+    pub(crate) fn set_track_history(&mut self, track: bool) {
+        assert_eq!(0, self.moves_played(), "moves have been played");
+        self.track_history = track;
+    }
+    /// Replaces the position with `partial`, reusing the allocated history.
+    pub(crate) fn reset(&mut self, partial: shogi_core::PartialPosition) {
+        self.base_ply = u32::from(partial.ply());
+        self.inner = PartialPosition::from(partial);
+        self.states.clear();
+        self.states.push(State::initial(&self.inner));
+        self.captures.clear();
+        self.moves.clear();
+        self.captured_stack.clear();
+    }
+    /// Undoes moves until the position returns to `ply`.
+    ///
+    /// Panics if `ply` is not within the current history, or if the history is not tracked
+    /// and `ply` is not the current one.
+    pub fn undo_to(&mut self, ply: u32) {
+        if !self.track_history {
+            assert_eq!(ply, self.ply(), "the history is not tracked");
+            return;
+        }
+        let Some(index) = self.history_index(ply) else {
+            panic!(
+                "ply {ply} is out of the history ({}..={})",
+                self.base_ply,
+                self.ply()
+            );
+        };
+        while self.states.len() > index + 1 {
+            let m = self.last_move().expect("no last move");
+            self.undo_move(m);
+        }
+    }
+    /// Returns an iterator over the positions after each of `moves`, applied in order.
+    ///
+    /// The iteration stops at the first move which is illegal in the position reached so far.
+    pub fn walk<'a>(&self, moves: &'a [Move]) -> impl Iterator<Item = Position> + 'a {
+        let mut pos = self.clone();
+        moves.iter().map_while(move |&m| {
+            if !pos.legal_moves().contains(&m) {
+                return None;
+            }
+            pos.do_move(m);
+            Some(pos.clone())
+        })
+    }
+    /// Returns a copy of the position without the history of the moves played.
+    ///
+    /// The copy can't undo the moves before it, and the repetitions and [`Position::game_hash`]
+    /// only take the positions after it into account. Its cost doesn't depend on the game length,
+    /// which suits cloning the root position for each search thread.
+    pub fn clone_shallow(&self) -> Position {
+        Self {
+            inner: self.inner.clone(),
+            states: vec![self.state().clone()],
+            captures: Vec::new(),
+            moves: Vec::new(),
+            rules: self.rules,
+            track_history: self.track_history,
+            captured_stack: Vec::new(),
+            base_ply: self.ply(),
+        }
+    }
+    /// Returns a copy of the position with `c` to move, without history.
+    ///
+    /// The board, hands and ply are unchanged, and the key differs only in the side to move.
+    pub fn with_side_to_move(&self, c: Color) -> Position {
+        if c == self.side_to_move() {
+            self.clone_shallow()
+        } else {
+            self.with_side_flipped()
+        }
+    }
+    /// Returns a copy of the position with the side to move flipped, without history.
+    pub(crate) fn with_side_flipped(&self) -> Position {
+        let mut inner = self.inner.clone();
+        inner.side = inner.side.flip();
+        let mut keys = self.state().keys;
+        keys.0 ^= Key::COLOR;
+        let checkers = AttackInfo::calculate_checkers(&inner);
+        let state = State {
+            keys,
+            captured: None,
+            last_moved: None,
+            last_move: None,
+            attack_info: AttackInfo::new(checkers, &inner),
+        };
+        Self {
+            inner,
+            states: vec![state],
+            captures: Vec::new(),
+            moves: Vec::new(),
+            rules: self.rules,
+            track_history: self.track_history,
+            captured_stack: Vec::new(),
+            base_ply: self.ply(),
+        }
+    }
+    /// Applies `m` and returns the new [`Position::key`].
+    #[inline(always)]
+    pub fn do_move_key(&mut self, m: Move) -> u64 {
+        self.do_move(m);
+        self.key()
+    }
+    /// Applies `m` and returns a token to reverse it with [`Position::undo_move_tok`].
+    ///
+    /// The token is a checked wrapper around the history kept by the position, not a
+    /// replacement for it: the move is undone from that history as with [`Position::undo_move`],
+    /// so tokens must be consumed in the reverse order of the moves.
+    pub fn do_move_tok(&mut self, m: Move) -> UndoToken {
+        let token = UndoToken {
+            m,
+            captured: self.piece_at(m.to()),
+            key: self.key(),
+        };
+        self.do_move(m);
+        token
+    }
+    /// Reverses the move of `token` with [`Position::undo_move`].
+    ///
+    /// Panics if `token` is not for the last move: its move and captured piece must match
+    /// the last entry of the history, and the key must return to the one before the move.
+    pub fn undo_move_tok(&mut self, token: UndoToken) {
+        assert!(
+            Some(token.m) == self.last_move() && token.captured == self.captured(),
+            "token is not for the last move"
+        );
+        self.undo_move(token.m);
+        assert_eq!(token.key, self.key(), "token is not for the last move");
     }
     #[inline(always)]
     pub(crate) fn player_bitboard(&self, c: Color) -> Bitboard {
@@ -255,6 +934,54 @@ impl Position {
         self.state().attack_info.pinned(c)
     }
     #[inline(always)]
+    pub(crate) fn promotable(&self, sq: Square, c: Color) -> bool {
+        PROMOTABLE[self.rules.promotion_ranks as usize][sq.array_index()][c.array_index()]
+    }
+    /// Calculates the deltas of the board key and the hand key applied by `m`.
+    fn keys_delta(&self, m: Move) -> (Key, Key) {
+        let c = self.side_to_move();
+        let mut keys = (Key::COLOR, Key::ZERO);
+        match m {
+            Move::Normal { from, to, promote } => {
+                let piece = self.inner.piece_at(from).unwrap();
+                if let Some(p) = self.inner.piece_at(to) {
+                    let pk = p.piece_kind();
+                    let pk_unpromoted = if let Some(pk) = pk.unpromote() {
+                        pk
+                    } else {
+                        pk
+                    };
+                    keys.0 ^= ZOBRIST_TABLE.board(to, p);
+                    keys.1 ^= ZOBRIST_TABLE.hand(
+                        c,
+                        pk_unpromoted,
+                        self.inner.hand_of_a_player(c).count(pk_unpromoted).unwrap(),
+                    );
+                }
+                let target_piece = if promote {
+                    piece.promote().unwrap()
+                } else {
+                    piece
+                };
+                keys.0 ^= ZOBRIST_TABLE.board(from, piece);
+                keys.0 ^= ZOBRIST_TABLE.board(to, target_piece);
+            }
+            Move::Drop { to, piece } => {
+                keys.1 ^= ZOBRIST_TABLE.hand(
+                    c,
+                    piece.piece_kind(),
+                    self.inner
+                        .hand_of_a_player(c)
+                        .count(piece.piece_kind())
+                        .unwrap()
+                        - 1,
+                );
+                keys.0 ^= ZOBRIST_TABLE.board(to, piece);
+            }
+        }
+        keys
+    }
+    #[inline(always)]
     fn state(&self) -> &State {
         self.states.last().expect("empty states")
     }
@@ -270,14 +997,29 @@ impl Default for Position {
     }
 }
 
-#[derive(Clone, Debug)]
+/// A move with the captured piece and the key before it, returned by [`Position::do_move_tok`].
+///
+/// It is checked against the history of the position when undone.
+#[must_use]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UndoToken {
+    m: Move,
+    captured: Option<Piece>,
+    key: u64,
+}
+
+#[derive(Clone, Debug)]
 pub(crate) struct PartialPosition {
     side: Color,
-    ply: u16,
     hands: [Hand; Color::NUM],
     board: [Option<Piece>; Square::NUM],
     player_bb: [Bitboard; Color::NUM],
     piece_bb: [Bitboard; PieceKind::NUM],
+    pawn_files: [u16; Color::NUM],
+    #[cfg(feature = "attack-maps")]
+    attack_counts: [[u8; Square::NUM]; Color::NUM], // 各マスに利いている駒の数
+    #[cfg(feature = "attack-maps")]
+    attack_maps: [Bitboard; Color::NUM], // 各手番の利きの範囲
 }
 
 impl PartialPosition {
@@ -287,14 +1029,108 @@ impl PartialPosition {
         let (pk, c) = p.to_parts();
         self.player_bb[c.array_index()] ^= single;
         self.piece_bb[pk.array_index()] ^= single;
+        // 二歩がない限り、各筋の歩は高々1枚
+        if pk == PieceKind::Pawn {
+            self.pawn_files[c.array_index()] ^= 1 << (sq.file() - 1);
+        }
     }
     #[inline(always)]
     fn piece_at(&self, sq: Square) -> Option<Piece> {
         self.board[sq.array_index()]
     }
+    /// Puts `p` on the empty square `sq`.
+    #[inline(always)]
+    fn put_piece(&mut self, sq: Square, p: Piece) {
+        self.toggle_piece(sq, p);
+        self.board[sq.array_index()] = Some(p);
+    }
+    /// Removes `p` from `sq`.
+    #[inline(always)]
+    fn remove_piece(&mut self, sq: Square, p: Piece) {
+        self.toggle_piece(sq, p);
+        self.board[sq.array_index()] = None;
+    }
+    /// Puts `p` on `sq` or removes it, updating the bitboards but not the board.
+    #[cfg(not(feature = "attack-maps"))]
+    #[inline(always)]
+    fn toggle_piece(&mut self, sq: Square, p: Piece) {
+        self.xor_piece(sq, p);
+    }
+    /// Puts `p` on `sq` or removes it, updating the bitboards and the attacks but not the board.
+    ///
+    /// Besides the attacks of `p` itself, only those of the sliding pieces whose rays reach `sq`
+    /// change, as they are blocked or unblocked there.
+    #[cfg(feature = "attack-maps")]
+    fn toggle_piece(&mut self, sq: Square, p: Piece) {
+        let (pk, c) = p.to_parts();
+        let old_occ = self.occupied_bitboard();
+        let sliders = self.sliders_to(sq, &old_occ);
+        let attacks = ATTACK_TABLE.attack(pk, sq, c, &old_occ);
+        let removed = old_occ.contains(sq);
+        self.xor_piece(sq, p);
+        let new_occ = self.occupied_bitboard();
+        for from in sliders {
+            let (pk, c) = self.board[from.array_index()].unwrap().to_parts();
+            let old = ATTACK_TABLE.attack(pk, from, c, &old_occ);
+            let new = ATTACK_TABLE.attack(pk, from, c, &new_occ);
+            self.remove_attacks(c, old & !new);
+            self.add_attacks(c, new & !old);
+        }
+        if removed {
+            self.remove_attacks(c, attacks);
+        } else {
+            self.add_attacks(c, attacks);
+        }
+    }
+    /// Returns the squares of the sliding pieces of both colors whose rays reach `sq` under `occ`.
+    #[cfg(feature = "attack-maps")]
+    #[rustfmt::skip]
+    fn sliders_to(&self, sq: Square, occ: &Bitboard) -> Bitboard {
+        let lances = self.piece_bb[PieceKind::Lance.array_index()];
+        // 先手の香は後手の香の利きの方向にある
+          (ATTACK_TABLE.ky.attack(sq, Color::White, occ) & lances & self.player_bb[Color::Black.array_index()])
+        | (ATTACK_TABLE.ky.attack(sq, Color::Black, occ) & lances & self.player_bb[Color::White.array_index()])
+        | (ATTACK_TABLE.ka.attack(sq, occ) & (self.piece_bb[PieceKind::Bishop.array_index()] | self.piece_bb[PieceKind::ProBishop.array_index()]))
+        | (ATTACK_TABLE.hi.attack(sq, occ) & (self.piece_bb[PieceKind::Rook.array_index()] | self.piece_bb[PieceKind::ProRook.array_index()]))
+    }
+    #[cfg(feature = "attack-maps")]
+    #[inline(always)]
+    fn add_attacks(&mut self, c: Color, bb: Bitboard) {
+        let counts = &mut self.attack_counts[c.array_index()];
+        for sq in bb {
+            counts[sq.array_index()] += 1;
+        }
+        self.attack_maps[c.array_index()] |= bb;
+    }
+    #[cfg(feature = "attack-maps")]
     #[inline(always)]
-    fn piece_at_mut(&mut self, sq: Square) -> &mut Option<Piece> {
-        &mut self.board[sq.array_index()]
+    fn remove_attacks(&mut self, c: Color, bb: Bitboard) {
+        let counts = &mut self.attack_counts[c.array_index()];
+        let mut vacated = Bitboard::empty();
+        for sq in bb {
+            let count = &mut counts[sq.array_index()];
+            *count -= 1;
+            if *count == 0 {
+                vacated |= Bitboard::single(sq);
+            }
+        }
+        self.attack_maps[c.array_index()] ^= vacated;
+    }
+    /// Calculates the numbers of attackers of each square and the attack maps from scratch.
+    #[cfg(feature = "attack-maps")]
+    fn calculate_attacks(&self) -> ([[u8; Square::NUM]; Color::NUM], [Bitboard; Color::NUM]) {
+        let occ = self.occupied_bitboard();
+        let mut counts = [[0; Square::NUM]; Color::NUM];
+        let mut maps = [Bitboard::empty(); Color::NUM];
+        for from in occ {
+            let (pk, c) = self.board[from.array_index()].unwrap().to_parts();
+            let attacks = ATTACK_TABLE.attack(pk, from, c, &occ);
+            for sq in attacks {
+                counts[c.array_index()][sq.array_index()] += 1;
+            }
+            maps[c.array_index()] |= attacks;
+        }
+        (counts, maps)
     }
     #[inline(always)]
     fn hand_of_a_player(&self, c: Color) -> Hand {
@@ -314,6 +1150,28 @@ impl PartialPosition {
             .into_iter()
             .next()
     }
+    /// Calculates the board key and the hand key from scratch.
+    fn keys(&self) -> (Key, Key) {
+        let mut keys = (Key::ZERO, Key::ZERO);
+        if self.side == Color::White {
+            keys.0 ^= Key::COLOR;
+        }
+        for sq in Square::all() {
+            if let Some(p) = self.board[sq.array_index()] {
+                keys.0 ^= ZOBRIST_TABLE.board(sq, p);
+            }
+        }
+        for c in Color::all() {
+            for pk in Hand::all_hand_pieces() {
+                if let Some(num) = self.hands[c.array_index()].count(pk) {
+                    for i in 0..num {
+                        keys.1 ^= ZOBRIST_TABLE.hand(c, pk, i);
+                    }
+                }
+            }
+        }
+        keys
+    }
 }
 
 impl From<shogi_core::PartialPosition> for PartialPosition {
@@ -322,6 +1180,7 @@ impl From<shogi_core::PartialPosition> for PartialPosition {
         let mut board = [None; Square::NUM];
         let mut player_bb = [Bitboard::empty(); Color::NUM];
         let mut piece_bb = [Bitboard::empty(); PieceKind::NUM];
+        let mut pawn_files = [0; Color::NUM];
         for c in Color::all() {
             hands[c.array_index()] = pp.hand_of_a_player(c);
         }
@@ -331,16 +1190,29 @@ impl From<shogi_core::PartialPosition> for PartialPosition {
             if let Some(p) = piece_at {
                 player_bb[p.color().array_index()] |= Bitboard::single(sq);
                 piece_bb[p.piece_kind().array_index()] |= Bitboard::single(sq);
+                if p.piece_kind() == PieceKind::Pawn {
+                    pawn_files[p.color().array_index()] |= 1 << (sq.file() - 1);
+                }
             }
         }
-        Self {
+        #[allow(unused_mut)]
+        let mut partial = Self {
             side: pp.side_to_move(),
-            ply: pp.ply(),
             hands,
             board,
             player_bb,
             piece_bb,
+            pawn_files,
+            #[cfg(feature = "attack-maps")]
+            attack_counts: [[0; Square::NUM]; Color::NUM],
+            #[cfg(feature = "attack-maps")]
+            attack_maps: [Bitboard::empty(); Color::NUM],
+        };
+        #[cfg(feature = "attack-maps")]
+        {
+            (partial.attack_counts, partial.attack_maps) = partial.calculate_attacks();
         }
+        partial
     }
 }
 
@@ -349,9 +1221,25 @@ struct State {
     keys: (Key, Key),
     captured: Option<Piece>,
     last_moved: Option<Piece>,
+    last_move: Option<Move>,
     attack_info: AttackInfo,
 }
 
+impl State {
+    /// Creates the state of a position without history.
+    fn initial(inner: &PartialPosition) -> Self {
+        let keys = inner.keys();
+        let checkers = AttackInfo::calculate_checkers(inner);
+        State {
+            keys,
+            captured: None,
+            last_moved: None,
+            last_move: None,
+            attack_info: AttackInfo::new(checkers, inner),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct AttackInfo {
     checkers: Bitboard,                     // 王手をかけている駒の位置
@@ -380,37 +1268,38 @@ impl AttackInfo {
                 }
             }
         }
-        if let Some(sq) = pos.king_position(opp) {
+        Self {
+            checkers,
+            checkables: Self::calculate_checkables(pos, opp),
+            pinned,
+        }
+    }
+    /// Calculates the squares from which each piece kind would give check to the king of `c`.
+    pub fn calculate_checkables(pos: &PartialPosition, c: Color) -> [Bitboard; PieceKind::NUM] {
+        if let Some(sq) = pos.king_position(c) {
+            let occ = pos.occupied_bitboard();
             let ka = ATTACK_TABLE.ka.attack(sq, &occ);
             let hi = ATTACK_TABLE.hi.attack(sq, &occ);
-            let ki = ATTACK_TABLE.ki.attack(sq, opp);
-            let ou = ATTACK_TABLE.ou.attack(sq, opp);
-            Self {
-                checkers,
-                checkables: [
-                    ATTACK_TABLE.fu.attack(sq, opp),
-                    ATTACK_TABLE.ky.attack(sq, opp, &occ),
-                    ATTACK_TABLE.ke.attack(sq, opp),
-                    ATTACK_TABLE.gi.attack(sq, opp),
-                    ki,
-                    ka,
-                    hi,
-                    Bitboard::empty(),
-                    ki,
-                    ki,
-                    ki,
-                    ki,
-                    ka | ou,
-                    hi | ou,
-                ],
-                pinned,
-            }
+            let ki = ATTACK_TABLE.ki.attack(sq, c);
+            let ou = ATTACK_TABLE.ou.attack(sq, c);
+            [
+                ATTACK_TABLE.fu.attack(sq, c),
+                ATTACK_TABLE.ky.attack(sq, c, &occ),
+                ATTACK_TABLE.ke.attack(sq, c),
+                ATTACK_TABLE.gi.attack(sq, c),
+                ki,
+                ka,
+                hi,
+                Bitboard::empty(),
+                ki,
+                ki,
+                ki,
+                ki,
+                ka | ou,
+                hi | ou,
+            ]
         } else {
-            Self {
-                checkers,
-                checkables: [Bitboard::empty(); PieceKind::NUM],
-                pinned,
-            }
+            [Bitboard::empty(); PieceKind::NUM]
         }
     }
     #[rustfmt::skip]
@@ -447,6 +1336,7 @@ impl AttackInfo {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::PositionBuilder;
     use shogi_core::PartialPosition;
     use shogi_usi_parser::FromUsi;
 
@@ -488,6 +1378,39 @@ mod tests {
         assert!(!pos.in_check());
     }
 
+    #[test]
+    fn turn_relative() {
+        let mut pos = Position::default();
+        assert_eq!(Color::Black, pos.us());
+        assert_eq!(Color::White, pos.them());
+        assert_eq!(0, pos.relative_rank(Square::SQ_5I));
+        assert_eq!(2, pos.relative_rank(Square::SQ_7G));
+        assert_eq!(8, pos.relative_rank(Square::SQ_1A));
+        let black = Square::all()
+            .map(|sq| pos.relative_rank(sq))
+            .collect::<Vec<_>>();
+        pos.do_move(Move::Normal {
+            from: Square::SQ_7G,
+            to: Square::SQ_7F,
+            promote: false,
+        });
+        assert_eq!(Color::White, pos.us());
+        assert_eq!(Color::Black, pos.them());
+        assert_eq!(0, pos.relative_rank(Square::SQ_5A));
+        assert_eq!(2, pos.relative_rank(Square::SQ_3C));
+        assert_eq!(8, pos.relative_rank(Square::SQ_9I));
+        // mirrored by the rotation of the board
+        for sq in Square::all() {
+            let rotated = Square::new(10 - sq.file(), 10 - sq.rank()).unwrap();
+            assert_eq!(
+                black[sq.array_index()],
+                pos.relative_rank(rotated),
+                "square: {:?}",
+                sq
+            );
+        }
+    }
+
     #[allow(clippy::bool_assert_comparison)]
     #[test]
     fn do_undo_move() {
@@ -561,55 +1484,1452 @@ mod tests {
     }
 
     #[test]
-    fn perft() {
-        fn perft(pos: &mut Position, depth: usize) -> u64 {
+    fn captures_history() {
+        let mut pos = Position::default();
+        assert!(pos.captures_history().is_empty());
+        // +7776FU,-3334FU,+8822UM,-3122GI,+0055KA,-2255GI
+        let moves = [
+            Move::Normal {
+                from: Square::SQ_7G,
+                to: Square::SQ_7F,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_3C,
+                to: Square::SQ_3D,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_8H,
+                to: Square::SQ_2B,
+                promote: true,
+            },
+            Move::Normal {
+                from: Square::SQ_3A,
+                to: Square::SQ_2B,
+                promote: false,
+            },
+            Move::Drop {
+                to: Square::SQ_5E,
+                piece: Piece::B_B,
+            },
+            Move::Normal {
+                from: Square::SQ_2B,
+                to: Square::SQ_5E,
+                promote: false,
+            },
+        ];
+        let expected = [
+            vec![],
+            vec![],
+            vec![Piece::W_B],
+            vec![Piece::W_B, Piece::B_PB],
+            vec![Piece::W_B, Piece::B_PB],
+            vec![Piece::W_B, Piece::B_PB, Piece::B_B],
+        ];
+        for (&m, e) in moves.iter().zip(&expected) {
+            pos.do_move(m);
+            assert_eq!(e.as_slice(), pos.captures_history());
+        }
+        for (i, &m) in moves.iter().enumerate().rev() {
+            pos.undo_move(m);
+            let e = if i == 0 {
+                &[][..]
+            } else {
+                &expected[i - 1][..]
+            };
+            assert_eq!(e, pos.captures_history());
+        }
+    }
+
+    #[test]
+    fn capture_promoted() {
+        let mut pos = Position::new(
+            PartialPosition::from_usi("sfen 4k4/9/9/9/4+r4/9/9/4R4/4K4 b - 1")
+                .expect("failed to parse"),
+        );
+        let (board_key, hand_key) = pos.keys();
+        let m = Move::Normal {
+            from: Square::SQ_5H,
+            to: Square::SQ_5E,
+            promote: false,
+        };
+        assert_eq!(Some((Square::SQ_5E, Piece::W_PR)), pos.capture_info(m));
+        assert_eq!(
+            None,
+            pos.capture_info(Move::Normal {
+                from: Square::SQ_5H,
+                to: Square::SQ_5F,
+                promote: false,
+            })
+        );
+        pos.do_move(m);
+        // 成駒は元の駒として持ち駒に加わる
+        assert_eq!(Some(1), pos.hand(Color::Black).count(PieceKind::Rook));
+        assert_eq!(None, pos.hand(Color::Black).count(PieceKind::ProRook));
+        assert_eq!(Some(Piece::W_PR), pos.captured());
+        assert_eq!(&[Piece::W_PR], pos.captures_history());
+        let expected = Position::new(
+            PartialPosition::from_usi("sfen 4k4/9/9/9/4R4/9/9/9/4K4 w R 2")
+                .expect("failed to parse"),
+        );
+        assert_eq!(expected.keys(), pos.keys());
+
+        pos.undo_move(m);
+        assert_eq!(Some(Piece::W_PR), pos.piece_at(Square::SQ_5E));
+        assert_eq!(Some(Piece::B_R), pos.piece_at(Square::SQ_5H));
+        assert_eq!(Some(0), pos.hand(Color::Black).count(PieceKind::Rook));
+        assert_eq!((board_key, hand_key), pos.keys());
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "key mismatch")]
+    fn debug_assert_sound() {
+        let mut pos = Position::default();
+        pos.debug_assert_sound();
+        // 盤面と bitboard を揃えたまま key だけを壊す
+        pos.states.last_mut().unwrap().keys.0 ^= Key::COLOR;
+        pos.debug_assert_sound();
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "occupancy mismatch")]
+    fn debug_assert_moved() {
+        let mut pos = Position::default();
+        // 動かす歩を駒種の bitboard からだけ消すと、その手の後で検出される
+        pos.inner.piece_bb[PieceKind::Pawn.array_index()] ^= Bitboard::single(Square::SQ_7G);
+        pos.do_move(Move::Normal {
+            from: Square::SQ_7G,
+            to: Square::SQ_7F,
+            promote: false,
+        });
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "player bitboard mismatch")]
+    fn debug_assert_sound_board() {
+        let mut pos = Position::default();
+        pos.inner.board[Square::SQ_5E.array_index()] = Some(Piece::B_G);
+        pos.debug_assert_sound();
+    }
+
+    #[test]
+    fn set_ply() {
+        let mut pos = Position::default();
+        pos.set_ply(Position::MAX_PLY - 1);
+        let m = Move::Normal {
+            from: Square::SQ_7G,
+            to: Square::SQ_7F,
+            promote: false,
+        };
+        pos.do_move(m);
+        assert_eq!(Position::MAX_PLY, pos.ply());
+        assert_eq!(Some(&[m][..]), pos.moves_since(Position::MAX_PLY - 1));
+        assert_eq!(None, pos.moves_since(Position::MAX_PLY - 2));
+        pos.undo_move(m);
+        assert_eq!(Position::MAX_PLY - 1, pos.ply());
+
+        pos.do_move(m);
+        pos.set_ply(2);
+        assert_eq!(Some(&[m][..]), pos.moves_since(1));
+        pos.undo_to(1);
+        assert_eq!(Position::default().to_sfen(), pos.to_sfen());
+    }
+
+    #[test]
+    fn ply_saturates() {
+        let m = Move::Normal {
+            from: Square::SQ_7G,
+            to: Square::SQ_7F,
+            promote: false,
+        };
+        // u16 の範囲を超えても続けられる
+        let mut pos = Position::from_sfen(
+            "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 65535",
+        )
+        .expect("failed to parse");
+        pos.do_move(m);
+        assert_eq!(65536, pos.ply());
+        assert!(pos.to_sfen().ends_with(" 65536"));
+
+        // 上限では増えも折り返しもしない
+        let mut pos = Position::from_sfen(&format!(
+            "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - {}",
+            Position::MAX_PLY
+        ))
+        .expect("failed to parse");
+        pos.do_move(m);
+        assert_eq!(Position::MAX_PLY, pos.ply());
+        pos.undo_move(m);
+        assert_eq!(Position::MAX_PLY, pos.ply());
+        assert_eq!(Position::default().keys(), pos.keys());
+    }
+
+    #[test]
+    #[should_panic(expected = "too small")]
+    fn set_ply_before_history() {
+        let mut pos = Position::default();
+        pos.do_move(Move::Normal {
+            from: Square::SQ_7G,
+            to: Square::SQ_7F,
+            promote: false,
+        });
+        pos.set_ply(1);
+    }
+
+    #[test]
+    fn moves_since() {
+        let mut pos = Position::default();
+        assert_eq!(Some(&[][..]), pos.moves_since(1));
+        assert_eq!(None, pos.moves_since(0));
+        assert_eq!(None, pos.moves_since(2));
+        for _ in 0..10 {
+            let m = pos.legal_moves_sorted()[0];
+            pos.do_move(m);
+        }
+        let snapshot = pos.clone_shallow();
+        let mut moves = Vec::new();
+        for i in 0..10 {
+            let legal = pos.legal_moves_sorted();
+            let m = legal[i % legal.len()];
+            pos.do_move(m);
+            moves.push(m);
+        }
+        let delta = pos.moves_since(snapshot.ply()).expect("out of history");
+        assert_eq!(moves.as_slice(), delta);
+        let mut client = snapshot.clone();
+        delta.iter().for_each(|&m| client.do_move(m));
+        assert_eq!(pos.keys(), client.keys());
+        assert_eq!(pos.to_sfen(), client.to_sfen());
+        assert_eq!(None, pos.moves_since(pos.ply() + 1));
+        assert_eq!(Some(&moves[9..]), pos.moves_since(pos.ply() - 1));
+        // 履歴のない複製
+        assert_eq!(None, snapshot.moves_since(1));
+
+        pos.undo_move(moves[9]);
+        assert_eq!(Some(&moves[..9]), pos.moves_since(snapshot.ply()));
+        pos.reset_to("4k4/9/9/9/9/9/9/9/4K4 b - 5", &[]).unwrap();
+        assert_eq!(Some(&[][..]), pos.moves_since(5));
+    }
+
+    #[test]
+    fn pawn_files() {
+        fn scan(pos: &Position, c: Color) -> u16 {
+            Square::all()
+                .filter(|&sq| pos.piece_at(sq) == Some(Piece::new(PieceKind::Pawn, c)))
+                .fold(0, |mask, sq| mask | 1 << (sq.file() - 1))
+        }
+
+        let mut pos = Position::default();
+        assert_eq!(0x1ff, pos.pawn_files(Color::Black));
+        assert_eq!(0x1ff, pos.pawn_files(Color::White));
+        // +2726FU,-8384FU,+2625FU,-8485FU,+2524FU,-2324FU,+2824HI,-8586FU,+8786FU,-0023FU,+2423RY
+        let moves = [
+            Move::Normal {
+                from: Square::SQ_2G,
+                to: Square::SQ_2F,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_8C,
+                to: Square::SQ_8D,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_2F,
+                to: Square::SQ_2E,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_8D,
+                to: Square::SQ_8E,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_2E,
+                to: Square::SQ_2D,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_2C,
+                to: Square::SQ_2D,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_2H,
+                to: Square::SQ_2D,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_8E,
+                to: Square::SQ_8F,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_8G,
+                to: Square::SQ_8F,
+                promote: false,
+            },
+            Move::Drop {
+                to: Square::SQ_2C,
+                piece: Piece::W_P,
+            },
+            Move::Normal {
+                from: Square::SQ_2D,
+                to: Square::SQ_2C,
+                promote: true,
+            },
+        ];
+        let expected = [
+            (0x1ff, 0x1ff),
+            (0x1ff, 0x1ff),
+            (0x1ff, 0x1ff),
+            (0x1ff, 0x1ff),
+            (0x1ff, 0x1ff),
+            // captured
+            (0x1fd, 0x1ff),
+            (0x1fd, 0x1fd),
+            (0x1fd, 0x1fd),
+            (0x1fd, 0x17d),
+            // dropped
+            (0x1fd, 0x17f),
+            (0x1fd, 0x17d),
+        ];
+        for (&m, &e) in moves.iter().zip(&expected) {
+            pos.do_move(m);
+            assert_eq!(
+                e,
+                (pos.pawn_files(Color::Black), pos.pawn_files(Color::White))
+            );
+            for c in Color::all() {
+                assert_eq!(scan(&pos, c), pos.pawn_files(c));
+            }
+        }
+        for &m in moves.iter().rev() {
+            pos.undo_move(m);
+            for c in Color::all() {
+                assert_eq!(scan(&pos, c), pos.pawn_files(c));
+            }
+        }
+        // promoted
+        let mut pos = PositionBuilder::new()
+            .place(Square::SQ_5I, Piece::B_K)
+            .place(Square::SQ_5A, Piece::W_K)
+            .place(Square::SQ_4D, Piece::B_P)
+            .build()
+            .expect("failed to build");
+        assert_eq!(0x008, pos.pawn_files(Color::Black));
+        pos.do_move(Move::Normal {
+            from: Square::SQ_4D,
+            to: Square::SQ_4C,
+            promote: true,
+        });
+        assert_eq!(0, pos.pawn_files(Color::Black));
+    }
+
+    #[test]
+    fn check_interposition_squares() {
+        let position =
+            |sfen| Position::new(PartialPosition::from_usi(sfen).expect("failed to parse"));
+        assert!(Position::default().check_interposition_squares().is_empty());
+        // 2マス離れた飛車による王手
+        let pos = position("sfen 4k4/9/9/9/9/9/4r4/9/4K4 b - 1");
+        assert_eq!(
+            Bitboard::single(Square::SQ_5H),
+            pos.check_interposition_squares()
+        );
+        // 遠い角による王手
+        let pos = position("sfen 4k4/9/9/9/b8/9/9/9/4K4 b - 1");
+        assert_eq!(
+            Bitboard::single(Square::SQ_8F)
+                | Bitboard::single(Square::SQ_7G)
+                | Bitboard::single(Square::SQ_6H),
+            pos.check_interposition_squares()
+        );
+        // 隣接する金、桂による王手
+        let pos = position("sfen 4k4/9/9/9/9/9/9/4g4/4K4 b - 1");
+        assert!(pos.in_check());
+        assert!(pos.check_interposition_squares().is_empty());
+        let pos = position("sfen 4k4/9/9/9/9/9/3n5/9/4K4 b - 1");
+        assert!(pos.in_check());
+        assert!(pos.check_interposition_squares().is_empty());
+        // 両王手
+        let pos = position("sfen 4k4/9/9/9/b8/9/4r4/9/4K4 b - 1");
+        assert_eq!(2, pos.checkers().count());
+        assert!(pos.check_interposition_squares().is_empty());
+    }
+
+    #[test]
+    fn attack_map() {
+        fn walk(pos: &mut Position, depth: usize) {
+            pos.debug_assert_sound();
+            let maps = Color::all().map(|c| pos.attack_map(c));
+            for c in Color::all() {
+                assert_eq!(pos.calculate_attack_map(c), maps[c.array_index()]);
+            }
+            // 利いている駒の数も全体の再計算と一致する
+            #[cfg(feature = "attack-maps")]
+            {
+                let (counts, _) = pos.inner.calculate_attacks();
+                assert_eq!(counts, pos.inner.attack_counts);
+            }
             if depth == 0 {
-                return 1;
+                return;
             }
-            let mut count = 0;
             for m in pos.legal_moves() {
                 pos.do_move(m);
-                count += perft(pos, depth - 1);
+                walk(pos, depth - 1);
                 pos.undo_move(m);
+                // 戻した後は保持していた値を返す
+                for c in Color::all() {
+                    assert_eq!(maps[c.array_index()], pos.attack_map(c));
+                }
             }
-            count
         }
 
-        // from default position
-        {
-            let mut pos = Position::default();
-            assert_eq!(30, perft(&mut pos, 1));
-            assert_eq!(900, perft(&mut pos, 2));
-            assert_eq!(25470, perft(&mut pos, 3));
-            assert_eq!(719731, perft(&mut pos, 4));
-        }
-        // from maximum moves
-        // https://qiita.com/ak11/items/8bd5f2bb0f5b014143c8#%E5%88%9D%E6%9C%9F%E5%B1%80%E9%9D%A2%E3%81%98%E3%82%83%E3%81%AA%E3%81%84%E5%B1%80%E9%9D%A2
-        // P1+HI *  *  *  *  *  *  *  *
-        // P2 *  * +OU * +GI * +GI+GI-OU
-        // P3 *  *  *  * +KA *  *  *  *
-        // P4 *  *  *  *  *  *  *  *  *
-        // P5 *  *  *  *  *  *  *  *  *
-        // P6 *  *  *  *  *  *  *  *  *
-        // P7 *  *  *  *  *  *  *  *  *
-        // P8 *  *  *  *  *  *  *  *  *
-        // P9 * +KY * +KY * +KY *  *  *
-        // P+00FU00KY00KE00GI00KI00KA00HI
-        // P-00AL
-        // +
-        {
-            let mut pos = Position::new(
+        walk(&mut Position::default(), 3);
+        walk(
+            &mut Position::new(
+                PartialPosition::from_usi(
+                    "sfen l6nl/5+P1gk/2np1S3/p1p4Pp/3P2Sp1/1PPb2P1P/P5GS1/R8/LN4bKL w RGgsn5p 1",
+                )
+                .expect("failed to parse"),
+            ),
+            2,
+        );
+        walk(
+            &mut Position::new(
                 PartialPosition::from_usi(
                     "sfen R8/2K1S1SSk/4B4/9/9/9/9/9/1L1L1L3 b RBGSNLP3g3n17p 1",
                 )
                 .expect("failed to parse"),
-            );
-            assert_eq!(593, perft(&mut pos, 1));
-            assert_eq!(105677, perft(&mut pos, 2));
-        }
+            ),
+            2,
+        );
     }
 
-    #[allow(clippy::bool_assert_comparison)]
+    #[cfg(feature = "attack-maps")]
+    #[test]
+    fn attack_count() {
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 4k4/9/4p4/9/2P1R2p1/9/4P4/9/4K4 b - 1")
+                .expect("failed to parse"),
+        );
+        // 5六には5五の飛車と5七の歩が利く
+        assert_eq!(2, pos.attack_count(Color::Black, Square::SQ_5F));
+        assert_eq!(1, pos.attack_count(Color::Black, Square::SQ_5G));
+        assert_eq!(0, pos.attack_count(Color::Black, Square::SQ_5B));
+        assert_eq!(0, pos.attack_count(Color::White, Square::SQ_5G));
+    }
+
+    #[test]
+    fn attacks_of() {
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 4k4/9/4p4/9/2P1R2p1/9/4P4/9/4K4 b - 1")
+                .expect("failed to parse"),
+        );
+        // 飛車の利きは各方向で最初の駒 (敵味方問わず) まで
+        let expected = [
+            Square::SQ_5C,
+            Square::SQ_5D,
+            Square::SQ_5F,
+            Square::SQ_5G,
+            Square::SQ_2E,
+            Square::SQ_3E,
+            Square::SQ_4E,
+            Square::SQ_6E,
+            Square::SQ_7E,
+        ]
+        .into_iter()
+        .fold(Bitboard::empty(), |bb, sq| bb | Bitboard::single(sq));
+        assert_eq!(expected, pos.attacks_of(Square::SQ_5E));
+        assert_eq!(
+            Bitboard::single(Square::SQ_2F),
+            pos.attacks_of(Square::SQ_2E)
+        );
+        assert!(pos.attacks_of(Square::SQ_5F).is_empty());
+    }
+
+    #[test]
+    fn attacks_if_dropped() {
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 4k4/9/4p4/9/9/9/4P4/9/4K4 b R 1")
+                .expect("failed to parse"),
+        );
+        // 飛車の利きは5三、5七の駒で止まる
+        let expected = [
+            Square::SQ_5C,
+            Square::SQ_5D,
+            Square::SQ_5F,
+            Square::SQ_5G,
+            Square::SQ_1E,
+            Square::SQ_2E,
+            Square::SQ_3E,
+            Square::SQ_4E,
+            Square::SQ_6E,
+            Square::SQ_7E,
+            Square::SQ_8E,
+            Square::SQ_9E,
+        ]
+        .into_iter()
+        .fold(Bitboard::empty(), |bb, sq| bb | Bitboard::single(sq));
+        assert_eq!(
+            expected,
+            pos.attacks_if_dropped(PieceKind::Rook, Color::Black, Square::SQ_5E)
+        );
+        assert_eq!(
+            Bitboard::single(Square::SQ_5D),
+            pos.attacks_if_dropped(PieceKind::Pawn, Color::Black, Square::SQ_5E)
+        );
+        assert_eq!(
+            Bitboard::single(Square::SQ_5F),
+            pos.attacks_if_dropped(PieceKind::Pawn, Color::White, Square::SQ_5E)
+        );
+        // 局面は変わらない
+        assert_eq!(None, pos.piece_at(Square::SQ_5E));
+    }
+
+    #[test]
+    fn is_defended() {
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 4k4/9/3gp4/9/9/6P2/9/9/4K4 b - 1")
+                .expect("failed to parse"),
+        );
+        // 金に守られた歩と浮き駒の歩
+        assert!(pos.is_defended(Square::SQ_5C));
+        assert!(!pos.is_defended(Square::SQ_3F));
+        assert!(!pos.is_defended(Square::SQ_5E));
+        assert!(!pos.is_defended(Square::SQ_6C));
+        assert!(Position::default().is_defended(Square::SQ_7G));
+    }
+
+    #[test]
+    fn king_attack() {
+        let position =
+            |sfen| Position::new(PartialPosition::from_usi(sfen).expect("failed to parse"));
+        #[rustfmt::skip]
+        let weights = PieceValues::new([
+            1, 1, 2, 2, 2, 3, 4, 0,
+            2, 2, 2, 2, 4, 5,
+        ]);
+        assert_eq!(0, Position::default().king_attacker_count(Color::Black));
+        assert_eq!(
+            0,
+            Position::default().king_attack_value(Color::White, &weights)
+        );
+
+        // 銀の利きが玉の周囲に届くかどうか
+        let pos = position("sfen 4k4/9/9/9/9/4s4/9/9/4K4 b - 1");
+        assert_eq!(0, pos.king_attacker_count(Color::Black));
+        let pos = position("sfen 4k4/9/9/9/9/9/4s4/9/4K4 b - 1");
+        assert_eq!(1, pos.king_attacker_count(Color::Black));
+        assert_eq!(2, pos.king_attack_value(Color::Black, &weights));
+        // 飛車を追加すると攻めの評価が増える
+        let pos = position("sfen 4k4/9/9/9/9/9/4s4/9/r3K4 b - 1");
+        assert_eq!(
+            Bitboard::single(Square::SQ_5G) | Bitboard::single(Square::SQ_9I),
+            pos.attackers_in_king_zone(Color::Black)
+        );
+        assert_eq!(2, pos.king_attacker_count(Color::Black));
+        assert_eq!(6, pos.king_attack_value(Color::Black, &weights));
+        assert_eq!(0, pos.king_attack_value(Color::White, &weights));
+    }
+
+    #[test]
+    fn try_rule_won() {
+        let partial =
+            PartialPosition::from_usi("sfen 8k/9/4K4/9/9/9/9/9/9 b - 1").expect("failed to parse");
+        let rules = Rules {
+            try_rule_square: Some(Square::SQ_5A),
+            ..Default::default()
+        };
+        let moves = [
+            Move::Normal {
+                from: Square::SQ_5C,
+                to: Square::SQ_5B,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_1A,
+                to: Square::SQ_1B,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_5B,
+                to: Square::SQ_5A,
+                promote: false,
+            },
+        ];
+        // 既定では無効
+        let mut pos = Position::new(partial.clone());
+        moves.iter().for_each(|&m| pos.do_move(m));
+        assert!(!pos.try_rule_won());
+
+        let mut pos = Position::with_rules(partial, rules);
+        for (i, &m) in moves.iter().enumerate() {
+            assert!(!pos.try_rule_won());
+            pos.do_move(m);
+            assert_eq!(i == 2, pos.try_rule_won());
+        }
+
+        // 後手の目標は5九
+        let pos = Position::with_rules(
+            PartialPosition::from_usi("sfen 9/9/9/9/9/9/9/9/K3k4 b - 2").expect("failed to parse"),
+            rules,
+        );
+        assert!(pos.try_rule_won());
+    }
+
+    #[test]
+    fn king_exposure() {
+        let position =
+            |sfen| Position::new(PartialPosition::from_usi(sfen).expect("failed to parse"));
+        // 裸の玉
+        let pos = position("sfen 4k4/9/9/9/9/9/9/9/4K4 b - 1");
+        assert_eq!(3, pos.king_open_files(Color::Black));
+        assert_eq!(5, pos.king_adjacent_empty(Color::Black));
+        assert_eq!(3, pos.king_open_files(Color::White));
+        // 美濃囲い風の玉
+        let pos = position("sfen 4k4/9/9/9/9/9/6PPP/6SK1/5G1NL b - 1");
+        assert_eq!(0, pos.king_open_files(Color::Black));
+        assert_eq!(2, pos.king_adjacent_empty(Color::Black));
+        // 端の玉は2筋だけを見る
+        let pos = position("sfen 4k4/9/9/9/9/9/7P1/8K/9 b - 1");
+        assert_eq!(1, pos.king_open_files(Color::Black));
+        assert_eq!(4, pos.king_adjacent_empty(Color::Black));
+        assert_eq!(
+            0,
+            position("sfen 4k4/9/9/9/9/9/9/9/9 b - 1").king_open_files(Color::Black)
+        );
+    }
+
+    #[test]
+    fn phase() {
+        assert_eq!(256, Position::default().phase());
+        // 成駒や持ち駒も元の駒として数える
+        let pos = Position::new(
+            PartialPosition::from_usi(
+                "sfen 8l/1l+R2P3/p2pBG1pp/kps1p4/Nn1P2G2/P1P1P2PP/1PS6/1KSG3+r1/LN2+p3L w Sbgn3p 124",
+            )
+            .expect("failed to parse"),
+        );
+        assert_eq!(256, pos.phase());
+        // 二枚落ちは飛車と角の分だけ小さい: 256 * 8865 / 10710
+        assert_eq!(211, Position::handicap(crate::Handicap::TwoPieces).phase());
+        assert_eq!(
+            0,
+            Position::new(
+                PartialPosition::from_usi("sfen 4k4/9/9/9/9/9/9/9/4K4 b 18P 1")
+                    .expect("failed to parse")
+            )
+            .phase()
+        );
+    }
+
+    #[test]
+    fn check_squares() {
+        let builder = PositionBuilder::new()
+            .place(Square::SQ_5I, Piece::B_K)
+            .place(Square::SQ_5E, Piece::W_K)
+            .place(Square::SQ_5G, Piece::B_P)
+            .place(Square::SQ_3E, Piece::W_G);
+        let pos = builder.clone().build().expect("failed to build");
+        let mut expected = Bitboard::empty();
+        for sq in [
+            Square::SQ_5A,
+            Square::SQ_5B,
+            Square::SQ_5C,
+            Square::SQ_5D,
+            Square::SQ_5F,
+            Square::SQ_5G,
+            Square::SQ_9E,
+            Square::SQ_8E,
+            Square::SQ_7E,
+            Square::SQ_6E,
+            Square::SQ_4E,
+            Square::SQ_3E,
+        ] {
+            expected |= Bitboard::single(sq);
+        }
+        let check_squares = pos.check_squares(Color::White);
+        assert_eq!(expected, check_squares[PieceKind::Rook.array_index()]);
+        assert_eq!(
+            Bitboard::single(Square::SQ_5F),
+            check_squares[PieceKind::Pawn.array_index()]
+        );
+        assert!(check_squares[PieceKind::King.array_index()].is_empty());
+        // calculated for the side to move as well
+        let pos = builder
+            .side_to_move(Color::White)
+            .build()
+            .expect("failed to build");
+        assert_eq!(check_squares, pos.check_squares(Color::White));
+    }
+
+    #[test]
+    fn walk() {
+        // +7776FU,-3334FU,+8822UM
+        let moves = [
+            Move::Normal {
+                from: Square::SQ_7G,
+                to: Square::SQ_7F,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_3C,
+                to: Square::SQ_3D,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_8H,
+                to: Square::SQ_2B,
+                promote: true,
+            },
+        ];
+        let mut pos = Position::default();
+        for &m in &moves {
+            pos.do_move(m);
+        }
+        let walked = Position::default().walk(&moves).collect::<Vec<_>>();
+        assert_eq!(3, walked.len());
+        assert_eq!(Some(moves[1]), walked[1].last_move());
+        assert_eq!(pos.key(), walked[2].key());
+        // stops at the illegal move
+        let illegal = [moves[0], moves[2]];
+        assert_eq!(1, Position::default().walk(&illegal).count());
+    }
+
+    #[test]
+    fn board_array() {
+        let pos = Position::default();
+        let board = pos.board_array();
+        for sq in Square::all() {
+            assert_eq!(pos.piece_at(sq), board[sq.array_index()]);
+        }
+        assert_eq!(Some(Piece::W_L), board[0]);
+        assert_eq!(Some(Piece::B_R), board[Square::SQ_2H.array_index()]);
+    }
+
+    #[test]
+    fn sfen_eq_ignoring_ply() {
+        let normal = |from, to| Move::Normal {
+            from,
+            to,
+            promote: false,
+        };
+        // +7776FU,-3334FU,+2726FU
+        let joined = [
+            normal(Square::SQ_7G, Square::SQ_7F),
+            normal(Square::SQ_3C, Square::SQ_3D),
+            normal(Square::SQ_2G, Square::SQ_2F),
+        ];
+        let mut pos0 = Position::default();
+        joined.iter().for_each(|&m| pos0.do_move(m));
+
+        // +5948OU,-5142OU,+4859OU,-4251OU を挟んで同一局面に戻る
+        let mut pos1 = Position::default();
+        [
+            normal(Square::SQ_5I, Square::SQ_4H),
+            normal(Square::SQ_5A, Square::SQ_4B),
+            normal(Square::SQ_4H, Square::SQ_5I),
+            normal(Square::SQ_4B, Square::SQ_5A),
+        ]
+        .iter()
+        .chain(joined.iter())
+        .for_each(|&m| pos1.do_move(m));
+        assert_ne!(pos0.ply(), pos1.ply());
+        assert!(pos0.sfen_eq_ignoring_ply(&pos1));
+        assert!(pos1.sfen_eq_ignoring_ply(&pos0));
+
+        // 手番が異なる
+        let mut pos2 = Position::default();
+        joined[..2].iter().for_each(|&m| pos2.do_move(m));
+        assert!(!pos0.sfen_eq_ignoring_ply(&pos2));
+        assert!(!pos0.sfen_eq_ignoring_ply(&Position::default()));
+    }
+
+    #[test]
+    fn keys_after() {
+        let normal = |from, to| Move::Normal {
+            from,
+            to,
+            promote: false,
+        };
+        let pos = Position::default();
+        assert_eq!(pos.key(), pos.keys_after(&[]));
+        // +7776FU,-3334FU,+2726FU と +2726FU,-3334FU,+7776FU は同一局面になる
+        let moves0 = [
+            normal(Square::SQ_7G, Square::SQ_7F),
+            normal(Square::SQ_3C, Square::SQ_3D),
+            normal(Square::SQ_2G, Square::SQ_2F),
+        ];
+        let moves1 = [moves0[2], moves0[1], moves0[0]];
+        assert_eq!(pos.keys_after(&moves0), pos.keys_after(&moves1));
+        assert_ne!(pos.keys_after(&moves0), pos.keys_after(&moves1[..2]));
+        for n in 0..=moves0.len() {
+            let mut expected = Position::default();
+            moves0[..n].iter().for_each(|&m| expected.do_move(m));
+            assert_eq!(expected.key(), pos.keys_after(&moves0[..n]));
+        }
+        // 局面は変更されない
+        assert_eq!(Position::default().keys(), pos.keys());
+        assert_eq!(1, pos.ply());
+    }
+
+    #[test]
+    fn history_sfens() {
+        let mut pos = Position::default();
+        assert_eq!(vec![pos.to_sfen()], pos.history_sfens());
+        let mut expected = vec![pos.to_sfen()];
+        for usi in ["7g7f", "3c3d", "8h2b+", "3a2b"] {
+            pos.play_usi(usi).expect("illegal move");
+            expected.push(pos.to_sfen());
+        }
+        let sfens = pos.history_sfens();
+        assert_eq!(5, sfens.len());
+        assert_eq!(expected, sfens);
+        assert_eq!(
+            "lnsgkg1nl/1r5s1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/7R1/LNSGKGSNL b Bb 5",
+            sfens[4]
+        );
+
+        // 履歴のない複製では現局面のみ
+        let shallow = pos.clone_shallow();
+        assert_eq!(vec![pos.to_sfen()], shallow.history_sfens());
+    }
+
+    #[test]
+    fn relative_key() {
+        let pos = Position::default();
+        assert_eq!(pos.key(), pos.relative_key());
+        // 先後反転した局面と一致する
+        for (sfen, mirrored) in [
+            (
+                "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+                "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1",
+            ),
+            (
+                "l6nl/5+P1gk/2np1S3/p1p4Pp/3P2Sp1/1PPb2P1P/P5GS1/R8/LN4bKL w RGgsn5p 1",
+                "lkB4nl/8r/1sg5p/p1p2Bpp1/1Ps2p3/Pp4P1P/3s1PN2/KG1+p5/LN6L b GSN5Prg 1",
+            ),
+        ] {
+            let pos = Position::from_sfen(sfen).expect("failed to parse");
+            let mirrored = Position::from_sfen(mirrored).expect("failed to parse");
+            assert_eq!(mirrored.key(), pos.relative_key());
+            assert_eq!(mirrored.relative_key(), pos.relative_key());
+            assert_ne!(pos.key(), pos.relative_key());
+        }
+    }
+
+    #[test]
+    fn with_side_to_move() {
+        let mut pos = Position::default();
+        pos.do_move(Move::Normal {
+            from: Square::SQ_7G,
+            to: Square::SQ_7F,
+            promote: false,
+        });
+        let same = pos.with_side_to_move(Color::White);
+        assert_eq!(pos.keys(), same.keys());
+        assert_eq!(pos.to_sfen(), same.to_sfen());
+
+        let flipped = pos.with_side_to_move(Color::Black);
+        assert_eq!(Color::Black, flipped.side_to_move());
+        assert_eq!(pos.ply(), flipped.ply());
+        assert!(pos.sfen_eq_ignoring_ply(&flipped.with_side_to_move(Color::White)));
+        assert_eq!(pos.keys().0 ^ Key::COLOR.value(), flipped.keys().0);
+        assert_eq!(pos.keys().1, flipped.keys().1);
+        let expected = Position::from_sfen(
+            "lnsgkgsnl/1r5b1/ppppppppp/9/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL b - 2",
+        )
+        .expect("failed to parse");
+        assert_eq!(expected.keys(), flipped.keys());
+        assert_eq!(expected.legal_moves().len(), flipped.legal_moves().len());
+    }
+
+    #[test]
+    fn clone_shallow() {
+        let mut pos = Position::default();
+        for m in [
+            Move::Normal {
+                from: Square::SQ_7G,
+                to: Square::SQ_7F,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_3C,
+                to: Square::SQ_3D,
+                promote: false,
+            },
+        ] {
+            pos.do_move(m);
+        }
+        let mut shallow = pos.clone_shallow();
+        assert_eq!(pos.key(), shallow.key());
+        assert_eq!(pos.ply(), shallow.ply());
+        assert_eq!(pos.last_move(), shallow.last_move());
+        assert_eq!(pos.legal_moves(), shallow.legal_moves());
+        let m = Move::Normal {
+            from: Square::SQ_8H,
+            to: Square::SQ_2B,
+            promote: true,
+        };
+        pos.do_move(m);
+        shallow.do_move(m);
+        assert_eq!(pos.key(), shallow.key());
+        shallow.undo_move(m);
+        shallow.undo_to(shallow.ply());
+        assert_eq!(pos.ply() - 1, shallow.ply());
+    }
+
+    #[test]
+    fn is_lone_king() {
+        assert!(!Position::default().is_lone_king(Color::Black));
+        // 持ち駒は数えない
+        let builder = PositionBuilder::new()
+            .place(Square::SQ_5I, Piece::B_K)
+            .place(Square::SQ_5A, Piece::W_K)
+            .place(Square::SQ_5C, Piece::W_P)
+            .hand(Color::Black, PieceKind::Rook, 2);
+        let pos = builder.build().expect("failed to build");
+        assert!(pos.is_lone_king(Color::Black));
+        assert!(!pos.is_lone_king(Color::White));
+        // 玉がない
+        let pos = PositionBuilder::new()
+            .place(Square::SQ_5I, Piece::B_G)
+            .place(Square::SQ_5A, Piece::W_K)
+            .build()
+            .expect("failed to build");
+        assert!(!pos.is_lone_king(Color::Black));
+        assert!(pos.is_lone_king(Color::White));
+    }
+
+    #[test]
+    fn king_in_enemy_camp() {
+        let pos = Position::default();
+        assert!(!pos.king_in_enemy_camp(Color::Black));
+        assert!(!pos.king_in_enemy_camp(Color::White));
+        // ▲5三玉 △9七玉
+        let builder = PositionBuilder::new()
+            .place(Square::SQ_5C, Piece::B_K)
+            .place(Square::SQ_9G, Piece::W_K);
+        let pos = builder.clone().build().expect("failed to build");
+        assert!(pos.king_in_enemy_camp(Color::Black));
+        assert!(pos.king_in_enemy_camp(Color::White));
+        let pos = builder
+            .rules(Rules {
+                promotion_ranks: 2,
+                ..Default::default()
+            })
+            .build()
+            .expect("failed to build");
+        assert!(!pos.king_in_enemy_camp(Color::Black));
+        assert!(!pos.king_in_enemy_camp(Color::White));
+    }
+
+    #[test]
+    fn undo_to() {
+        let mut pos = Position::default();
+        let root = pos.ply();
+        let moves = [
+            Move::Normal {
+                from: Square::SQ_7G,
+                to: Square::SQ_7F,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_3C,
+                to: Square::SQ_3D,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_8H,
+                to: Square::SQ_2B,
+                promote: true,
+            },
+        ];
+        pos.do_move(moves[0]);
+        let saved = (pos.ply(), pos.key());
+        for &m in &moves[1..] {
+            pos.do_move(m);
+        }
+        pos.undo_to(saved.0);
+        assert_eq!(saved, (pos.ply(), pos.key()));
+        pos.undo_to(root);
+        let default = Position::default();
+        assert_eq!(default.ply(), pos.ply());
+        assert_eq!(default.key(), pos.key());
+        assert_eq!(default.keys(), pos.keys());
+        for sq in Square::all() {
+            assert_eq!(default.piece_at(sq), pos.piece_at(sq));
+        }
+        for c in Color::all() {
+            assert_eq!(default.hand(c), pos.hand(c));
+        }
+        assert_eq!(None, pos.last_move());
+    }
+
+    #[test]
+    #[should_panic(expected = "out of the history")]
+    fn undo_to_out_of_history() {
+        let mut pos = Position::default();
+        pos.undo_to(pos.ply() + 1);
+    }
+
+    #[test]
+    fn repetition_count() {
+        let normal = |from, to| Move::Normal {
+            from,
+            to,
+            promote: false,
+        };
+        let mut pos = Position::default();
+        // 異なる手順で初期局面に戻っても数える
+        for (king_b, king_w) in [
+            (Square::SQ_4H, Square::SQ_4B),
+            (Square::SQ_5H, Square::SQ_5B),
+            (Square::SQ_6H, Square::SQ_6B),
+        ] {
+            let count = pos.repetition_count().unwrap();
+            pos.do_move(normal(Square::SQ_5I, king_b));
+            pos.do_move(normal(Square::SQ_5A, king_w));
+            assert_eq!(Some(1), pos.repetition_count());
+            pos.do_move(normal(king_b, Square::SQ_5I));
+            pos.do_move(normal(king_w, Square::SQ_5A));
+            assert_eq!(Some(count + 1), pos.repetition_count());
+        }
+        assert_eq!(Some(4), pos.repetition_count());
+        assert!(pos.can_claim_repetition_draw());
+    }
+
+    #[test]
+    fn can_claim_repetition_draw() {
+        let mut pos = Position::default();
+        // +5958OU,-5152OU,+5859OU,-5251OU
+        let moves = [
+            Move::Normal {
+                from: Square::SQ_5I,
+                to: Square::SQ_5H,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_5A,
+                to: Square::SQ_5B,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_5H,
+                to: Square::SQ_5I,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_5B,
+                to: Square::SQ_5A,
+                promote: false,
+            },
+        ];
+        assert!(!pos.can_claim_repetition_draw());
+        assert_eq!(Some(1), pos.repetition_count());
+        for i in 0..12 {
+            pos.do_move(moves[i % 4]);
+            // each position recurs every 4 moves
+            assert_eq!(Some((i + 1) / 4 + 1), pos.repetition_count());
+            // the initial position occurs for the 4th time after 12 moves
+            assert_eq!(i == 11, pos.can_claim_repetition_draw());
+        }
+        pos.undo_move(moves[3]);
+        assert!(!pos.can_claim_repetition_draw());
+        assert_eq!(Some(3), pos.repetition_count());
+    }
+
+    #[test]
+    fn must_promote() {
+        let pos = Position::new(
+            PartialPosition::from_usi("sfen 4k4/P8/9/2N6/9/9/9/9/4K4 b - 1")
+                .expect("failed to parse"),
+        );
+        let normal = |from, to, promote| Move::Normal { from, to, promote };
+        // 最終段への歩の移動
+        assert!(pos.must_promote(normal(Square::SQ_9B, Square::SQ_9A, false)));
+        assert!(pos.must_promote(normal(Square::SQ_9B, Square::SQ_9A, true)));
+        // 2段目への桂の移動
+        assert!(pos.must_promote(normal(Square::SQ_7D, Square::SQ_8B, false)));
+        assert!(!pos.must_promote(normal(Square::SQ_5I, Square::SQ_5H, false)));
+        assert!(!pos.must_promote(Move::Drop {
+            to: Square::SQ_5E,
+            piece: Piece::B_P,
+        }));
+        let legal = pos.legal_moves();
+        assert!(!legal.contains(&normal(Square::SQ_9B, Square::SQ_9A, false)));
+        assert!(legal.contains(&normal(Square::SQ_9B, Square::SQ_9A, true)));
+    }
+
+    #[allow(clippy::bool_assert_comparison)]
+    #[test]
+    fn is_recapture() {
+        let mut pos = Position::default();
+        assert_eq!(None, pos.last_move());
+        let m = Move::Normal {
+            from: Square::SQ_2G,
+            to: Square::SQ_2F,
+            promote: false,
+        };
+        assert_eq!(false, pos.is_recapture(m));
+        // +2726FU,-8384FU,+2625FU,-8485FU,+2524FU
+        for m in [
+            m,
+            Move::Normal {
+                from: Square::SQ_8C,
+                to: Square::SQ_8D,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_2F,
+                to: Square::SQ_2E,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_8D,
+                to: Square::SQ_8E,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_2E,
+                to: Square::SQ_2D,
+                promote: false,
+            },
+        ] {
+            pos.do_move(m);
+            assert_eq!(Some(m), pos.last_move());
+        }
+        // -2324FU
+        let recapture = Move::Normal {
+            from: Square::SQ_2C,
+            to: Square::SQ_2D,
+            promote: false,
+        };
+        assert_eq!(true, pos.is_recapture(recapture));
+        assert_eq!(
+            false,
+            pos.is_recapture(Move::Normal {
+                from: Square::SQ_8E,
+                to: Square::SQ_8F,
+                promote: false,
+            })
+        );
+        pos.do_move(recapture);
+        // +2824HI
+        assert_eq!(
+            true,
+            pos.is_recapture(Move::Normal {
+                from: Square::SQ_2H,
+                to: Square::SQ_2D,
+                promote: false,
+            })
+        );
+    }
+
+    #[test]
+    fn undo_token() {
+        let moves = [
+            Move::Normal {
+                from: Square::SQ_7G,
+                to: Square::SQ_7F,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_3C,
+                to: Square::SQ_3D,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_8H,
+                to: Square::SQ_2B,
+                promote: true,
+            },
+            Move::Normal {
+                from: Square::SQ_3A,
+                to: Square::SQ_2B,
+                promote: false,
+            },
+        ];
+        let mut pos = Position::default();
+        let mut keys = vec![pos.key()];
+        let mut tokens = Vec::new();
+        for &m in moves.iter() {
+            tokens.push(pos.do_move_tok(m));
+            keys.push(pos.key());
+        }
+        let mut stack_pos = Position::default();
+        for &m in moves.iter() {
+            stack_pos.do_move(m);
+        }
+        assert_eq!(stack_pos.key(), pos.key());
+        for (&m, token) in moves.iter().zip(tokens).rev() {
+            keys.pop();
+            pos.undo_move_tok(token);
+            stack_pos.undo_move(m);
+            assert_eq!(keys.last(), Some(&pos.key()));
+            assert_eq!(stack_pos.key(), pos.key());
+            assert!(Square::all().all(|sq| pos.piece_at(sq) == stack_pos.piece_at(sq)));
+            assert_eq!(stack_pos.hand(Color::White), pos.hand(Color::White));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "token is not for the last move")]
+    fn undo_token_out_of_order() {
+        let mut pos = Position::default();
+        let t0 = pos.do_move_tok(Move::Normal {
+            from: Square::SQ_7G,
+            to: Square::SQ_7F,
+            promote: false,
+        });
+        let _t1 = pos.do_move_tok(Move::Normal {
+            from: Square::SQ_3C,
+            to: Square::SQ_3D,
+            promote: false,
+        });
+        pos.undo_move_tok(t0);
+    }
+
+    #[test]
+    fn key_delta() {
+        let mut pos = Position::default();
+        let moves = [
+            // normal move
+            Move::Normal {
+                from: Square::SQ_7G,
+                to: Square::SQ_7F,
+                promote: false,
+            },
+            Move::Normal {
+                from: Square::SQ_3C,
+                to: Square::SQ_3D,
+                promote: false,
+            },
+            // capture with promotion
+            Move::Normal {
+                from: Square::SQ_8H,
+                to: Square::SQ_2B,
+                promote: true,
+            },
+            // capture of a promoted piece
+            Move::Normal {
+                from: Square::SQ_3A,
+                to: Square::SQ_2B,
+                promote: false,
+            },
+            // drop
+            Move::Drop {
+                to: Square::SQ_5E,
+                piece: Piece::B_B,
+            },
+        ];
+        for m in moves {
+            let expected = pos.key() ^ pos.key_delta(m);
+            pos.do_move(m);
+            assert_eq!(expected, pos.key(), "move: {:?}", m);
+        }
+    }
+
+    #[test]
+    fn children() {
+        let mut pos = Position::new(
+            PartialPosition::from_usi(
+                "sfen 8l/1l+R2P3/p2pBG1pp/kps1p4/Nn1P2G2/P1P1P2PP/1PS6/1KSG3+r1/LN2+p3L w Sbgn3p 124",
+            )
+            .expect("failed to parse"),
+        );
+        let children = pos.children();
+        assert_eq!(pos.legal_moves().len(), children.len());
+        for (m, key) in children {
+            pos.do_move(m);
+            assert_eq!(key, pos.key(), "move: {:?}", m);
+            pos.undo_move(m);
+        }
+
+        // 手順前後で同じ局面に合流する
+        let normal = |from, to| Move::Normal {
+            from,
+            to,
+            promote: false,
+        };
+        let child_key = |moves: [Move; 2], last: Move| {
+            let mut pos = Position::default();
+            moves.into_iter().for_each(|m| pos.do_move(m));
+            pos.children()
+                .into_iter()
+                .find(|&(m, _)| m == last)
+                .map(|(_, key)| key)
+                .expect("not a legal move")
+        };
+        let m76 = normal(Square::SQ_7G, Square::SQ_7F);
+        let m34 = normal(Square::SQ_3C, Square::SQ_3D);
+        let m26 = normal(Square::SQ_2G, Square::SQ_2F);
+        assert_eq!(child_key([m76, m34], m26), child_key([m26, m34], m76));
+    }
+
+    #[test]
+    fn key_without() {
+        let pos = Position::new(
+            PartialPosition::from_usi(
+                "sfen ln1g3nl/1r1sk1gs1/p1ppppb1p/1p4pp1/9/2P3P2/PP1PPP2P/1BG3SR1/LNS1KG1NL w - 24",
+            )
+            .expect("failed to parse"),
+        );
+        for sq in Square::all() {
+            match pos.piece_at(sq) {
+                Some(p) => {
+                    assert_ne!(pos.key(), pos.key_without(sq));
+                    assert_eq!(
+                        pos.key(),
+                        pos.key_without(sq) ^ ZOBRIST_TABLE.board(sq, p).value()
+                    );
+                }
+                None => assert_eq!(pos.key(), pos.key_without(sq)),
+            }
+        }
+        // 駒を取り除いた局面の key と一致する
+        let pos = Position::default();
+        let expected =
+            Position::from_sfen("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSN1 b - 1")
+                .expect("failed to parse");
+        assert_eq!(expected.key(), pos.key_without(Square::SQ_1I));
+    }
+
+    #[test]
+    fn do_move_key() {
+        let mut pos0 = Position::default();
+        let mut pos1 = Position::default();
+        for _ in 0..20 {
+            let m = pos0.legal_moves_sorted()[0];
+            pos0.do_move(m);
+            assert_eq!(pos0.key(), pos1.do_move_key(m));
+            assert_eq!(pos0.keys(), pos1.keys());
+        }
+    }
+
+    #[test]
+    fn game_hash() {
+        let m0 = Move::Normal {
+            from: Square::SQ_7G,
+            to: Square::SQ_7F,
+            promote: false,
+        };
+        let m1 = Move::Normal {
+            from: Square::SQ_3C,
+            to: Square::SQ_3D,
+            promote: false,
+        };
+        let m2 = Move::Normal {
+            from: Square::SQ_2G,
+            to: Square::SQ_2F,
+            promote: false,
+        };
+        let play = |moves: &[Move]| {
+            let mut pos = Position::default();
+            moves.iter().for_each(|&m| pos.do_move(m));
+            pos
+        };
+        let pos0 = play(&[m0, m1, m2]);
+        let pos1 = play(&[m2, m1, m0]);
+        assert_eq!(pos0.key(), pos1.key());
+        assert_ne!(pos0.game_hash(), pos1.game_hash());
+        assert_eq!(pos0.game_hash(), play(&[m0, m1, m2]).game_hash());
+        assert_ne!(pos0.game_hash(), play(&[m0, m1]).game_hash());
+        assert_ne!(Position::default().game_hash(), play(&[m0]).game_hash());
+
+        // 実行や環境に依らない固定値
+        assert_eq!(0x04d0_c783_6831_5d00, Position::default().game_hash());
+        assert_eq!(0xb48a_707f_273e_90f3, pos0.game_hash());
+    }
+
+    #[test]
+    fn perft() {
+        fn perft(pos: &mut Position, depth: usize) -> u64 {
+            if depth == 0 {
+                return 1;
+            }
+            let mut count = 0;
+            for m in pos.legal_moves() {
+                pos.do_move(m);
+                count += perft(pos, depth - 1);
+                pos.undo_move(m);
+            }
+            count
+        }
+
+        // from default position
+        {
+            let mut pos = Position::default();
+            assert_eq!(30, perft(&mut pos, 1));
+            assert_eq!(900, perft(&mut pos, 2));
+            assert_eq!(25470, perft(&mut pos, 3));
+            assert_eq!(719731, perft(&mut pos, 4));
+        }
+        // from maximum moves
+        // https://qiita.com/ak11/items/8bd5f2bb0f5b014143c8#%E5%88%9D%E6%9C%9F%E5%B1%80%E9%9D%A2%E3%81%98%E3%82%83%E3%81%AA%E3%81%84%E5%B1%80%E9%9D%A2
+        // P1+HI *  *  *  *  *  *  *  *
+        // P2 *  * +OU * +GI * +GI+GI-OU
+        // P3 *  *  *  * +KA *  *  *  *
+        // P4 *  *  *  *  *  *  *  *  *
+        // P5 *  *  *  *  *  *  *  *  *
+        // P6 *  *  *  *  *  *  *  *  *
+        // P7 *  *  *  *  *  *  *  *  *
+        // P8 *  *  *  *  *  *  *  *  *
+        // P9 * +KY * +KY * +KY *  *  *
+        // P+00FU00KY00KE00GI00KI00KA00HI
+        // P-00AL
+        // +
+        {
+            let mut pos = Position::new(
+                PartialPosition::from_usi(
+                    "sfen R8/2K1S1SSk/4B4/9/9/9/9/9/1L1L1L3 b RBGSNLP3g3n17p 1",
+                )
+                .expect("failed to parse"),
+            );
+            assert_eq!(593, perft(&mut pos, 1));
+            assert_eq!(105677, perft(&mut pos, 2));
+        }
+    }
+
+    #[test]
+    fn do_undo_without_history() {
+        // 履歴を記録しなくても同じ局面に戻る
+        fn walk(pos: &mut Position, depth: usize) -> u64 {
+            if depth == 0 {
+                return 1;
+            }
+            let key = pos.key();
+            let checkers = pos.checkers();
+            let mut count = 0;
+            for m in pos.legal_moves() {
+                pos.do_move(m);
+                count += walk(pos, depth - 1);
+                pos.undo_move(m);
+                assert_eq!(key, pos.key());
+                assert_eq!(checkers, pos.checkers());
+            }
+            count
+        }
+
+        let mut pos = Position::new(
+            PartialPosition::from_usi(
+                "sfen l6nl/5+P1gk/2np1S3/p1p4Pp/3P2Sp1/1PPb2P1P/P5GS1/R8/LN4bKL w RGgsn5p 1",
+            )
+            .expect("failed to parse"),
+        );
+        let expected = walk(&mut pos.clone(), 2);
+        pos.set_track_history(false);
+        assert_eq!(expected, walk(&mut pos, 2));
+        assert_eq!(1, pos.ply());
+    }
+
+    #[allow(clippy::bool_assert_comparison)]
     #[test]
     fn is_check_move() {
         // P1 *  *  *  *  *  * -FU * -OU
@@ -623,10 +2943,24 @@ mod tests {
         // P9 *  *  *  *  *  *  *  *  *
         // P-00AL
         // +
-        let pos = Position::new(
-            PartialPosition::from_usi("sfen 6p1k/9/6P1G/9/8L/9/9/9/9 b RBLrb3g4s4n2l16p 1")
-                .expect("failed to parse"),
-        );
+        let pos = PositionBuilder::new()
+            .place(Square::SQ_3A, Piece::W_P)
+            .place(Square::SQ_1A, Piece::W_K)
+            .place(Square::SQ_3C, Piece::B_P)
+            .place(Square::SQ_1C, Piece::B_G)
+            .place(Square::SQ_1E, Piece::B_L)
+            .hand(Color::Black, PieceKind::Rook, 1)
+            .hand(Color::Black, PieceKind::Bishop, 1)
+            .hand(Color::Black, PieceKind::Lance, 1)
+            .hand(Color::White, PieceKind::Rook, 1)
+            .hand(Color::White, PieceKind::Bishop, 1)
+            .hand(Color::White, PieceKind::Gold, 3)
+            .hand(Color::White, PieceKind::Silver, 4)
+            .hand(Color::White, PieceKind::Knight, 4)
+            .hand(Color::White, PieceKind::Lance, 2)
+            .hand(Color::White, PieceKind::Pawn, 16)
+            .build()
+            .expect("failed to build");
         let test_cases = [
             (
                 Move::Drop {
@@ -0,0 +1,21 @@
+use shogi_core::Square;
+
+/// Rule parameters of the game, for experimenting with variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rules {
+    /// Number of ranks of the promotion zone (`0..=9`), counted from the opponent's back rank.
+    pub promotion_ranks: u8,
+    /// Target square of the try rule for Black, if enabled. White's target is the flipped square.
+    ///
+    /// A player wins by moving the king onto the target, e.g. 5a (the opponent king's start square).
+    pub try_rule_square: Option<Square>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            promotion_ranks: 3,
+            try_rule_square: None,
+        }
+    }
+}
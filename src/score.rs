@@ -0,0 +1,86 @@
+use std::ops;
+
+/// A search score: either an ordinary evaluation or a distance to mate.
+///
+/// Scores are backed by `i32`. Values in `(-MATE_THRESHOLD, MATE_THRESHOLD)` are evaluations,
+/// and values outside of it (up to `±MATE`) are mates, where `MATE - n` means mate in `n` plies.
+/// Comparing scores prefers shorter mates and longer resistance against mates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Score(i32);
+
+impl Score {
+    pub const ZERO: Score = Score(0);
+    pub const MATE: i32 = 32000;
+    /// Maximum distance to mate in plies which can be represented.
+    pub const MAX_MATE_PLY: u32 = 1000;
+    pub const MATE_THRESHOLD: i32 = Self::MATE - Self::MAX_MATE_PLY as i32;
+
+    /// Creates an evaluation score. Panics in debug builds if `value` is within the mate window.
+    pub const fn new(value: i32) -> Score {
+        debug_assert!(-Self::MATE_THRESHOLD < value && value < Self::MATE_THRESHOLD);
+        Score(value)
+    }
+    /// The score of the side to move mating in `ply` plies.
+    pub const fn mate_in(ply: u32) -> Score {
+        debug_assert!(ply <= Self::MAX_MATE_PLY);
+        Score(Self::MATE - ply as i32)
+    }
+    /// The score of the side to move being mated in `ply` plies.
+    pub const fn mated_in(ply: u32) -> Score {
+        debug_assert!(ply <= Self::MAX_MATE_PLY);
+        Score(-Self::MATE + ply as i32)
+    }
+    pub const fn value(self) -> i32 {
+        self.0
+    }
+    pub const fn is_mate(self) -> bool {
+        self.0 >= Self::MATE_THRESHOLD || self.0 <= -Self::MATE_THRESHOLD
+    }
+    /// Returns the distance to mate in plies, positive if the side to move mates.
+    pub const fn mate_ply(self) -> Option<i32> {
+        if self.0 >= Self::MATE_THRESHOLD {
+            Some(Self::MATE - self.0)
+        } else if self.0 <= -Self::MATE_THRESHOLD {
+            Some(-Self::MATE - self.0)
+        } else {
+            None
+        }
+    }
+}
+
+impl ops::Neg for Score {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Score(-self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ordering() {
+        let scores = [
+            Score::mate_in(1),
+            Score::mate_in(3),
+            Score::new(100),
+            Score::ZERO,
+            Score::new(-100),
+            Score::mated_in(3),
+            Score::mated_in(1),
+        ];
+        assert!(scores.windows(2).all(|w| w[0] > w[1]));
+        assert_eq!(Score::mated_in(2), -Score::mate_in(2));
+    }
+
+    #[test]
+    fn mate_ply() {
+        assert_eq!(Some(3), Score::mate_in(3).mate_ply());
+        assert_eq!(Some(-4), Score::mated_in(4).mate_ply());
+        assert_eq!(None, Score::new(1234).mate_ply());
+        assert!(Score::mate_in(Score::MAX_MATE_PLY).is_mate());
+        assert!(!Score::new(Score::MATE_THRESHOLD - 1).is_mate());
+    }
+}
@@ -0,0 +1,347 @@
+use crate::bitboard::Bitboard;
+use crate::{MoveList, PieceValues, Position};
+use shogi_core::{Color, Move, PieceKind, Square};
+
+/// Piece kinds in ascending order of [`PieceValues::DEFAULT`], to find the least valuable attacker.
+const ORDERED_PIECE_KINDS: [PieceKind; PieceKind::NUM] = [
+    PieceKind::Pawn,
+    PieceKind::Lance,
+    PieceKind::Knight,
+    PieceKind::Silver,
+    PieceKind::Gold,
+    PieceKind::ProPawn,
+    PieceKind::ProLance,
+    PieceKind::ProKnight,
+    PieceKind::ProSilver,
+    PieceKind::Bishop,
+    PieceKind::ProBishop,
+    PieceKind::Rook,
+    PieceKind::ProRook,
+    PieceKind::King,
+];
+
+impl Position {
+    /// Returns the static exchange evaluation of `m` by the side to move, with [`PieceValues::DEFAULT`].
+    ///
+    /// Both sides recapture on the destination with their least valuable attacker
+    /// and may stop whenever continuing loses material.
+    /// Promotions are counted only for `m` itself, not for the recaptures.
+    pub fn see(&self, m: Move) -> i32 {
+        self.see_with(m, &PieceValues::DEFAULT)
+    }
+    /// Returns the static exchange evaluation of `m` with the given `values`.
+    pub fn see_with(&self, m: Move, values: &PieceValues) -> i32 {
+        let to = m.to();
+        let mut occ = self.occupied_bitboard();
+        let mut gains = Vec::with_capacity(32);
+        let on_to = match m {
+            Move::Normal { from, promote, .. } => {
+                let pk = self.piece_at(from).expect("no piece to move").piece_kind();
+                let captured = self
+                    .piece_at(to)
+                    .map_or(0, |p| values.value(p.piece_kind()));
+                occ ^= Bitboard::single(from);
+                if promote {
+                    gains.push(captured + values.promotion_gain(pk));
+                    values.value(pk.promote().expect("unpromotable piece"))
+                } else {
+                    gains.push(captured);
+                    values.value(pk)
+                }
+            }
+            Move::Drop { piece, .. } => {
+                occ |= Bitboard::single(to);
+                gains.push(0);
+                values.value(piece.piece_kind())
+            }
+        };
+        let mut on_to = on_to;
+        let mut c = self.side_to_move().flip();
+        while let Some((sq, pk)) = self.least_valuable_attacker(c, to, &occ) {
+            // 相手に取り返される場所には玉で取れない
+            if pk == PieceKind::King
+                && self
+                    .least_valuable_attacker(c.flip(), to, &(occ ^ Bitboard::single(sq)))
+                    .is_some()
+            {
+                break;
+            }
+            let last = *gains.last().unwrap();
+            gains.push(on_to - last);
+            occ ^= Bitboard::single(sq);
+            on_to = values.value(pk);
+            c = c.flip();
+        }
+        while gains.len() > 1 {
+            let last = gains.pop().unwrap();
+            let prev = gains.last_mut().unwrap();
+            *prev = -(-*prev).max(last);
+        }
+        gains[0]
+    }
+    /// Returns the material gained immediately by `m` with [`PieceValues::DEFAULT`]:
+    /// the value of the captured piece plus the promotion gain.
+    ///
+    /// Unlike [`Position::see`], recaptures are not considered.
+    pub fn move_gain(&self, m: Move) -> i32 {
+        self.move_gain_with(m, &PieceValues::DEFAULT)
+    }
+    /// Returns the material gained immediately by `m` with the given `values`.
+    pub fn move_gain_with(&self, m: Move, values: &PieceValues) -> i32 {
+        match m {
+            Move::Normal { from, to, promote } => {
+                let captured = self
+                    .piece_at(to)
+                    .map_or(0, |p| values.value(p.piece_kind()));
+                if promote {
+                    let pk = self.piece_at(from).expect("no piece to move").piece_kind();
+                    captured + values.promotion_gain(pk)
+                } else {
+                    captured
+                }
+            }
+            Move::Drop { .. } => 0,
+        }
+    }
+    /// Returns the legal moves with a non-negative [`Position::see`].
+    ///
+    /// A move to a square the opponent does not attack always has a non-negative SEE,
+    /// so this keeps those and the moves to attacked squares defended well enough that the
+    /// exchange does not lose material. Pieces left hanging on other squares are not considered.
+    pub fn safe_moves(&self) -> MoveList {
+        let mut moves = self.legal_moves();
+        moves.retain(|m| self.see(*m) >= 0);
+        moves
+    }
+    /// Returns the squares of the pieces of the side to move which the opponent could capture
+    /// with a positive [`Position::see`] if it were the opponent's turn.
+    pub fn threats(&self) -> Bitboard {
+        let c = self.side_to_move();
+        let flipped = self.with_side_flipped();
+        let mut bb = Bitboard::empty();
+        for to in self.attack_map(c.flip()) & self.player_bitboard(c) {
+            let threatened = self
+                .attackers_to(c.flip(), to, &self.occupied_bitboard())
+                .into_iter()
+                .any(|from| {
+                    flipped.see(Move::Normal {
+                        from,
+                        to,
+                        promote: false,
+                    }) > 0
+                });
+            if threatened {
+                bb |= Bitboard::single(to);
+            }
+        }
+        bb
+    }
+    /// Returns `true` if the side to move is not in check
+    /// and no legal capture has a positive [`Position::see`].
+    pub fn is_quiet(&self) -> bool {
+        !self.in_check() && !self.has_winning_capture()
+    }
+    /// Returns `true` if a legal capture has a positive [`Position::see`].
+    pub fn has_winning_capture(&self) -> bool {
+        self.capture_moves().iter().any(|&m| self.see(m) > 0)
+    }
+    fn least_valuable_attacker(
+        &self,
+        c: Color,
+        to: Square,
+        occ: &Bitboard,
+    ) -> Option<(Square, PieceKind)> {
+        let attackers = self.attackers_to(c, to, occ) & *occ;
+        if attackers.is_empty() {
+            return None;
+        }
+        ORDERED_PIECE_KINDS.into_iter().find_map(|pk| {
+            (attackers & self.piece_kind_bitboard(pk))
+                .into_iter()
+                .next()
+                .map(|sq| (sq, pk))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shogi_core::PartialPosition;
+    use shogi_usi_parser::FromUsi;
+
+    fn position(sfen: &str) -> Position {
+        Position::new(PartialPosition::from_usi(sfen).expect("failed to parse"))
+    }
+
+    #[test]
+    fn see() {
+        let values = PieceValues::DEFAULT;
+        // 浮き駒の飛車を歩で取る
+        let pos = position("sfen 4k4/9/9/4r4/4P4/9/9/9/4K4 b - 1");
+        assert_eq!(
+            values.value(PieceKind::Rook),
+            pos.see(Move::Normal {
+                from: Square::SQ_5E,
+                to: Square::SQ_5D,
+                promote: false,
+            })
+        );
+        // 金で守られた歩を飛車で取る
+        let pos = position("sfen 4k4/9/3g5/4p4/9/9/9/4R4/4K4 b - 1");
+        assert_eq!(
+            values.value(PieceKind::Pawn) - values.value(PieceKind::Rook),
+            pos.see(Move::Normal {
+                from: Square::SQ_5H,
+                to: Square::SQ_5D,
+                promote: false,
+            })
+        );
+        // 取り返されない場所への駒打ち
+        assert_eq!(
+            0,
+            position("sfen 4k4/9/9/9/9/9/9/9/4K4 b G 1").see(Move::Drop {
+                to: Square::SQ_5E,
+                piece: shogi_core::Piece::B_G,
+            })
+        );
+        // 成りの価値を含む
+        let pos = position("sfen 4k4/9/9/9/9/9/9/9/B3K4 b - 1");
+        assert_eq!(
+            values.promotion_gain(PieceKind::Bishop),
+            pos.see(Move::Normal {
+                from: Square::SQ_9I,
+                to: Square::SQ_1A,
+                promote: true,
+            })
+        );
+        // 玉は守られた駒を取れない
+        let pos = position("sfen 9/9/9/9/4k4/4pg3/4G4/4K4/9 w - 1");
+        assert_eq!(
+            values.value(PieceKind::Gold),
+            pos.see(Move::Normal {
+                from: Square::SQ_5F,
+                to: Square::SQ_5G,
+                promote: false,
+            })
+        );
+        let pos = position("sfen 9/9/9/9/4k4/4p4/4G4/4K4/9 w - 1");
+        assert_eq!(
+            values.value(PieceKind::Gold) - values.value(PieceKind::Pawn),
+            pos.see(Move::Normal {
+                from: Square::SQ_5F,
+                to: Square::SQ_5G,
+                promote: false,
+            })
+        );
+        // 飛車の後ろの飛車も取り返しに参加するので、歩を取ると飛車を損する
+        let pos = position("sfen 4k4/4r4/4r4/9/4p4/9/4R4/4R4/4K4 b - 1");
+        assert_eq!(
+            values.value(PieceKind::Pawn) - values.value(PieceKind::Rook),
+            pos.see(Move::Normal {
+                from: Square::SQ_5G,
+                to: Square::SQ_5E,
+                promote: false,
+            })
+        );
+    }
+
+    #[test]
+    fn move_gain() {
+        let values = PieceValues::DEFAULT;
+        // 金を取りながら角が成る
+        let pos = position("sfen 4k4/9/2g6/9/9/9/9/9/B3K4 b - 1");
+        assert_eq!(
+            values.value(PieceKind::Gold) + values.promotion_gain(PieceKind::Bishop),
+            pos.move_gain(Move::Normal {
+                from: Square::SQ_9I,
+                to: Square::SQ_7C,
+                promote: true,
+            })
+        );
+        // 取り返しは考慮しない
+        let pos = position("sfen 4k4/9/3g5/4p4/9/9/9/4R4/4K4 b - 1");
+        let m = Move::Normal {
+            from: Square::SQ_5H,
+            to: Square::SQ_5D,
+            promote: false,
+        };
+        assert_eq!(values.value(PieceKind::Pawn), pos.move_gain(m));
+        assert!(pos.see(m) < pos.move_gain(m));
+        assert_eq!(
+            0,
+            Position::default().move_gain(Move::Normal {
+                from: Square::SQ_7G,
+                to: Square::SQ_7F,
+                promote: false,
+            })
+        );
+    }
+
+    #[test]
+    fn threats() {
+        // 先手の飛車が歩の利きにいて、金は守られている
+        let pos = position("sfen 4k4/9/9/4p4/4R4/9/3G5/3S5/4K4 b - 1");
+        assert_eq!(Bitboard::single(Square::SQ_5E), pos.threats());
+        // 飛車を逃げれば脅威はない
+        let pos = position("sfen 4k4/9/9/4p4/9/4R4/3G5/3S5/4K4 b - 1");
+        assert!(pos.threats().is_empty());
+    }
+
+    #[test]
+    fn is_quiet() {
+        assert!(Position::default().is_quiet());
+        // 浮き駒の飛車を歩で取れる
+        assert!(!position("sfen 4k4/9/9/4r4/4P4/9/9/9/4K4 b - 1").is_quiet());
+        // 守られた歩を飛車で取るのは損
+        assert!(position("sfen 4k4/9/3g5/4p4/9/9/9/4R4/4K4 b - 1").is_quiet());
+        // 王手されている
+        assert!(!position("sfen 4k4/9/9/9/9/9/9/4r4/4K4 b - 1").is_quiet());
+    }
+
+    #[test]
+    fn has_winning_capture() {
+        assert!(!Position::default().has_winning_capture());
+        // 浮き駒の飛車
+        assert!(position("sfen 4k4/9/9/4r4/4P4/9/9/9/4K4 b - 1").has_winning_capture());
+        // 守られた歩しか取れない
+        assert!(!position("sfen 4k4/9/3g5/4p4/9/9/9/4R4/4K4 b - 1").has_winning_capture());
+        // 王手されていても取れる
+        let pos = position("sfen 4k4/9/9/9/9/9/9/4r4/4K4 b - 1");
+        assert!(pos.has_winning_capture());
+        assert!(!pos.is_quiet());
+    }
+
+    #[test]
+    fn safe_moves() {
+        assert_eq!(
+            Position::default().legal_moves(),
+            Position::default().safe_moves()
+        );
+        // 歩の利きに銀を出すと只で取られる
+        let pos = position("sfen 4k4/9/9/4p4/9/4S4/9/9/4K4 b - 1");
+        let hanging = Move::Normal {
+            from: Square::SQ_5F,
+            to: Square::SQ_5E,
+            promote: false,
+        };
+        assert!(pos.legal_moves().contains(&hanging));
+        let moves = pos.safe_moves();
+        assert!(!moves.contains(&hanging));
+        assert!(moves.contains(&Move::Normal {
+            from: Square::SQ_5F,
+            to: Square::SQ_4E,
+            promote: false,
+        }));
+        assert_eq!(pos.legal_moves().len() - 1, moves.len());
+        // 取り返せても銀と歩の交換は損
+        let pos = position("sfen 4k4/9/9/4p4/9/3GS4/9/9/4K4 b - 1");
+        assert!(!pos.safe_moves().contains(&hanging));
+        // 歩の利きに歩を打つのは交換なので安全
+        let pos = position("sfen 4k4/9/9/4p4/9/3G5/9/9/4K4 b P 1");
+        assert!(pos.safe_moves().contains(&Move::Drop {
+            to: Square::SQ_5E,
+            piece: shogi_core::Piece::B_P,
+        }));
+    }
+}
@@ -0,0 +1,608 @@
+use crate::hand::HandExt;
+use crate::hand::{count_pieces, HAND_PIECE_NUMS};
+use crate::piece::PieceExt;
+use crate::{PlayError, Position};
+use shogi_core::{Color, Hand, Move, PartialPosition, Piece, PieceKind, Square};
+use std::fmt;
+
+/// An error that can occur while parsing SFEN.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SfenError {
+    /// The board field is malformed.
+    InvalidBoard,
+    /// The side to move field is neither `b` nor `w`.
+    InvalidSide,
+    /// The hand field is malformed.
+    InvalidHand,
+    /// The move number field is not a positive number.
+    InvalidPly,
+    /// The number of fields is wrong.
+    InvalidFields,
+    /// A side doesn't have exactly one king.
+    InvalidKings,
+    /// A side has two unpromoted pawns on a file (二歩).
+    DoublePawns,
+    /// There are more pieces of a kind than a full set has.
+    TooManyPieces,
+    /// A piece is on a square where it can never move (行き所のない駒).
+    StuckPiece,
+}
+
+impl fmt::Display for SfenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SfenError::InvalidBoard => f.write_str("invalid board"),
+            SfenError::InvalidSide => f.write_str("invalid side to move"),
+            SfenError::InvalidHand => f.write_str("invalid hand"),
+            SfenError::InvalidPly => f.write_str("invalid move number"),
+            SfenError::InvalidFields => f.write_str("invalid number of fields"),
+            SfenError::InvalidKings => f.write_str("each side must have exactly one king"),
+            SfenError::DoublePawns => f.write_str("two pawns on a file"),
+            SfenError::TooManyPieces => f.write_str("too many pieces"),
+            SfenError::StuckPiece => f.write_str("stuck piece"),
+        }
+    }
+}
+
+impl std::error::Error for SfenError {}
+
+/// An error that can occur while parsing a USI `position` command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionCommandError {
+    /// The line is not `position startpos ...` or `position sfen ...`,
+    /// or has something other than `moves` after the position.
+    InvalidCommand,
+    /// The SFEN is malformed.
+    Sfen(SfenError),
+    /// The move at the index in the move list is malformed or illegal.
+    Move(usize, PlayError),
+}
+
+impl fmt::Display for PositionCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PositionCommandError::InvalidCommand => f.write_str("invalid position command"),
+            PositionCommandError::Sfen(e) => write!(f, "{e}"),
+            PositionCommandError::Move(i, e) => write!(f, "{e} at move {}", i + 1),
+        }
+    }
+}
+
+impl std::error::Error for PositionCommandError {}
+
+/// Unpromoted piece kinds and their letters for Black.
+const PIECE_CHARS: [(PieceKind, char); 8] = [
+    (PieceKind::Pawn, 'P'),
+    (PieceKind::Lance, 'L'),
+    (PieceKind::Knight, 'N'),
+    (PieceKind::Silver, 'S'),
+    (PieceKind::Gold, 'G'),
+    (PieceKind::Bishop, 'B'),
+    (PieceKind::Rook, 'R'),
+    (PieceKind::King, 'K'),
+];
+
+fn parse_piece(c: char, promoted: bool) -> Option<Piece> {
+    let pk = PIECE_CHARS
+        .iter()
+        .find(|&&(_, ch)| ch == c.to_ascii_uppercase())
+        .map(|&(pk, _)| pk)?;
+    let pk = if promoted { pk.promote()? } else { pk };
+    let color = if c.is_ascii_uppercase() {
+        Color::Black
+    } else {
+        Color::White
+    };
+    Some(Piece::new(pk, color))
+}
+
+fn piece_to_sfen(p: Piece) -> String {
+    let (pk, c) = p.to_parts();
+    let (promoted, pk) = match pk.unpromote() {
+        Some(pk) => (true, pk),
+        None => (false, pk),
+    };
+    let ch = PIECE_CHARS
+        .iter()
+        .find(|&&(k, _)| k == pk)
+        .map(|&(_, ch)| ch)
+        .expect("unknown piece kind");
+    let ch = match c {
+        Color::Black => ch,
+        Color::White => ch.to_ascii_lowercase(),
+    };
+    if promoted {
+        format!("+{ch}")
+    } else {
+        ch.to_string()
+    }
+}
+
+fn parse_board(s: &str) -> Option<[Option<Piece>; Square::NUM]> {
+    let mut board = [None; Square::NUM];
+    let rows = s.split('/').collect::<Vec<_>>();
+    if rows.len() != 9 {
+        return None;
+    }
+    for (rank, row) in (1..=9).zip(rows) {
+        let mut file = 10u8;
+        let mut promoted = false;
+        for c in row.chars() {
+            if promoted && !c.is_ascii_alphabetic() {
+                return None;
+            }
+            match c {
+                '+' => promoted = true,
+                '1'..='9' => file = file.checked_sub(c as u8 - b'0')?,
+                _ => {
+                    file = file.checked_sub(1)?;
+                    let sq = Square::new(file, rank)?;
+                    board[sq.array_index()] = Some(parse_piece(c, promoted)?);
+                    promoted = false;
+                }
+            }
+        }
+        if file != 1 || promoted {
+            return None;
+        }
+    }
+    Some(board)
+}
+
+/// Parses the hands of both players (e.g. `"RBGSNLP3g3n17p"`, or `"-"` if both are empty).
+fn parse_hands(s: &str) -> Option<[Hand; Color::NUM]> {
+    let mut hands = [Hand::default(); Color::NUM];
+    if s == "-" {
+        return Some(hands);
+    }
+    let mut tokens = [String::new(), String::new()];
+    let mut num = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            num.push(c);
+            continue;
+        }
+        let color = if c.is_ascii_uppercase() {
+            Color::Black
+        } else {
+            Color::White
+        };
+        let token = &mut tokens[color.array_index()];
+        token.push_str(&num);
+        token.push(c);
+        num.clear();
+    }
+    if !num.is_empty() || s.is_empty() {
+        return None;
+    }
+    for c in Color::all() {
+        let token = &tokens[c.array_index()];
+        if !token.is_empty() {
+            hands[c.array_index()] = Hand::from_sfen(token).ok()?;
+        }
+    }
+    Some(hands)
+}
+
+/// Parses `s` into the position and the ply, which may exceed the range of [`PartialPosition::ply`].
+fn parse_sfen(s: &str) -> Result<(PartialPosition, u32), SfenError> {
+    let s = s.strip_prefix("sfen ").unwrap_or(s);
+    let fields = s.split_ascii_whitespace().collect::<Vec<_>>();
+    let [board, side, hand, ply] = fields[..] else {
+        return Err(SfenError::InvalidFields);
+    };
+    let board = parse_board(board).ok_or(SfenError::InvalidBoard)?;
+    let side = match side {
+        "b" => Color::Black,
+        "w" => Color::White,
+        _ => return Err(SfenError::InvalidSide),
+    };
+    let hands = parse_hands(hand).ok_or(SfenError::InvalidHand)?;
+    let ply = match ply.parse::<u32>() {
+        Ok(ply) if ply > 0 => ply,
+        _ => return Err(SfenError::InvalidPly),
+    };
+
+    let mut partial = PartialPosition::empty();
+    for sq in Square::all() {
+        partial.piece_set(sq, board[sq.array_index()]);
+    }
+    for c in Color::all() {
+        *partial.hand_of_a_player_mut(c) = hands[c.array_index()];
+    }
+    partial.side_to_move_set(side);
+    Ok((partial, ply))
+}
+
+/// Checks that `s` is a well-formed SFEN of a plausible game position, without building a [`Position`].
+///
+/// In addition to the syntax checked by [`Position::from_sfen`], each side must have exactly one king
+/// and no two pawns on a file, no piece may be stuck where it can't move,
+/// and the numbers of pieces must not exceed a full set.
+pub fn validate(s: &str) -> Result<(), SfenError> {
+    let (partial, _) = parse_sfen(s)?;
+    let mut kings = [0; Color::NUM];
+    let mut pawn_files = [[false; 9]; Color::NUM];
+    for sq in Square::all() {
+        if partial
+            .piece_at(sq)
+            .is_some_and(|p| !p.has_legal_destination(sq))
+        {
+            return Err(SfenError::StuckPiece);
+        }
+        match partial.piece_at(sq).map(Piece::to_parts) {
+            Some((PieceKind::King, c)) => kings[c.array_index()] += 1,
+            Some((PieceKind::Pawn, c)) => {
+                let file = &mut pawn_files[c.array_index()][sq.file() as usize - 1];
+                if *file {
+                    return Err(SfenError::DoublePawns);
+                }
+                *file = true;
+            }
+            _ => {}
+        }
+    }
+    if kings != [1, 1] {
+        return Err(SfenError::InvalidKings);
+    }
+    for (pk, num) in Hand::all_hand_pieces().zip(HAND_PIECE_NUMS) {
+        if count_pieces(&partial, pk) > num {
+            return Err(SfenError::TooManyPieces);
+        }
+    }
+    Ok(())
+}
+
+impl Position {
+    /// Parses a position written in SFEN, e.g. `"lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1"`.
+    ///
+    /// A leading `"sfen "` is accepted. Only the syntax is checked, not the legality of the position.
+    pub fn from_sfen(s: &str) -> Result<Position, SfenError> {
+        let (partial, ply) = parse_sfen(s)?;
+        let mut pos = Position::new(partial);
+        pos.set_ply(ply);
+        Ok(pos)
+    }
+    /// Parses a USI `position` command, e.g. `"position startpos moves 7g7f 3c3d"`
+    /// or `"position sfen <board> <side> <hands> <ply> moves ..."`.
+    ///
+    /// Returns the starting position and the moves, which are checked to be legal in turn.
+    pub fn from_usi_position_command(
+        line: &str,
+    ) -> Result<(Position, Vec<Move>), PositionCommandError> {
+        let mut tokens = line.split_ascii_whitespace();
+        if tokens.next() != Some("position") {
+            return Err(PositionCommandError::InvalidCommand);
+        }
+        let (partial, ply) = match tokens.next() {
+            Some("startpos") => (PartialPosition::startpos(), 1),
+            Some("sfen") => {
+                let fields = tokens.by_ref().take(4).collect::<Vec<_>>();
+                parse_sfen(&fields.join(" ")).map_err(PositionCommandError::Sfen)?
+            }
+            _ => return Err(PositionCommandError::InvalidCommand),
+        };
+        let mut start = Position::new(partial);
+        start.set_ply(ply);
+        let mut moves = Vec::new();
+        match tokens.next() {
+            None => {}
+            Some("moves") => {
+                let mut pos = start.clone();
+                for (i, usi) in tokens.enumerate() {
+                    let m = pos
+                        .play_usi(usi)
+                        .map_err(|e| PositionCommandError::Move(i, e))?;
+                    moves.push(m);
+                }
+            }
+            Some(_) => return Err(PositionCommandError::InvalidCommand),
+        }
+        Ok((start, moves))
+    }
+    /// Resets the position to `sfen` and applies `moves`, reusing the allocated history.
+    ///
+    /// The rules are kept. `moves` must be legal. On error, the position is left unchanged.
+    pub fn reset_to(&mut self, sfen: &str, moves: &[Move]) -> Result<(), SfenError> {
+        let (partial, ply) = parse_sfen(sfen)?;
+        self.reset(partial);
+        self.set_ply(ply);
+        for &m in moves {
+            self.do_move(m);
+        }
+        Ok(())
+    }
+    /// Formats the position in SFEN, without the leading `"sfen "`.
+    pub fn to_sfen(&self) -> String {
+        let mut rows = Vec::with_capacity(9);
+        for rank in 1..=9 {
+            let mut row = String::new();
+            let mut empty = 0;
+            for file in (1..=9).rev() {
+                let sq = Square::new(file, rank).unwrap();
+                match self.piece_at(sq) {
+                    Some(p) => {
+                        if empty > 0 {
+                            row.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        row.push_str(&piece_to_sfen(p));
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                row.push_str(&empty.to_string());
+            }
+            rows.push(row);
+        }
+        let mut hands = String::new();
+        for c in Color::all() {
+            let hand = self.hand(c);
+            if hand != Hand::default() {
+                let s = hand.to_sfen();
+                match c {
+                    Color::Black => hands.push_str(&s),
+                    Color::White => hands.push_str(&s.to_ascii_lowercase()),
+                }
+            }
+        }
+        if hands.is_empty() {
+            hands.push('-');
+        }
+        let side = match self.side_to_move() {
+            Color::Black => 'b',
+            Color::White => 'w',
+        };
+        format!("{} {side} {hands} {}", rows.join("/"), self.ply())
+    }
+    /// Returns a hash of the SFEN of `self` without the ply.
+    ///
+    /// Unlike [`Position::key`], which depends on the zobrist table, this is the 64-bit FNV-1a
+    /// hash of the SFEN bytes and stays the same across versions and processes.
+    pub fn canonical_hash(&self) -> u64 {
+        let sfen = self.to_sfen();
+        let (sfen, _ply) = sfen.rsplit_once(' ').expect("SFEN has a ply");
+        sfen.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shogi_usi_parser::FromUsi;
+
+    #[test]
+    fn sfen() {
+        for sfen in [
+            "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1",
+            "R8/2K1S1SSk/4B4/9/9/9/9/9/1L1L1L3 b RBGSNLP3g3n17p 1",
+            "ln1g3nl/1r1sk1gs1/p1ppppb1p/1p4pp1/9/2P3P2/PP1PPP2P/1BG3SR1/LNS1KG1NL w - 24",
+            "8l/1l+R2P3/p2pBG1pp/kps1p4/Nn1P2G2/P1P1P2PP/1PS6/1KSG3+r1/LN2+p3L w Sbgn3p 124",
+        ] {
+            let pos = Position::from_sfen(sfen).expect("failed to parse");
+            assert_eq!(sfen, pos.to_sfen());
+            let partial =
+                PartialPosition::from_usi(&format!("sfen {sfen}")).expect("failed to parse");
+            assert_eq!(Position::new(partial.clone()).key(), pos.key());
+            assert_eq!(partial.to_sfen_owned(), pos.to_sfen());
+        }
+        assert_eq!(
+            Position::default().key(),
+            Position::from_sfen(
+                "sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1"
+            )
+            .expect("failed to parse")
+            .key()
+        );
+    }
+
+    #[test]
+    fn validate() {
+        for sfen in [
+            "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1",
+            "sfen 8l/1l+R2P3/p2pBG1pp/kps1p4/Nn1P2G2/P1P1P2PP/1PS6/1KSG3+r1/LN2+p3L w Sbgn3p 124",
+            "4k4/9/9/9/9/9/9/9/4K4 b 2r2b4g4s4n4l18p 1",
+        ] {
+            assert_eq!(Ok(()), super::validate(sfen), "{sfen}");
+        }
+        for (sfen, err) in [
+            ("9/9/9/9/9/9/9/9/9 b - 1 2", SfenError::InvalidFields),
+            ("4k4/9/9/9/9/9/9/9/4K3 b - 1", SfenError::InvalidBoard),
+            ("4k4/9/9/9/9/9/9/9/4K4 x - 1", SfenError::InvalidSide),
+            ("4k4/9/9/9/9/9/9/9/4K4 b 19P 1", SfenError::InvalidHand),
+            ("4k4/9/9/9/9/9/9/9/4K4 b - 0", SfenError::InvalidPly),
+            ("9/9/9/9/9/9/9/9/4K4 b - 1", SfenError::InvalidKings),
+            ("4k4/9/9/9/9/9/9/9/3KK4 b - 1", SfenError::InvalidKings),
+            ("4k4/9/9/9/9/4P4/9/4P4/4K4 b - 1", SfenError::DoublePawns),
+            ("4k4/4p4/9/9/9/4p4/9/9/4K4 b - 1", SfenError::DoublePawns),
+            ("4k4/9/9/9/9/9/9/9/4K4 b 3R 1", SfenError::TooManyPieces),
+            ("4kP3/9/9/9/9/9/9/9/4K4 b - 1", SfenError::StuckPiece),
+            ("4k4/9/9/9/9/9/9/n8/4K4 b - 1", SfenError::StuckPiece),
+            (
+                "4k4/9/9/9/9/9/9/+R+R2R4/4K4 b - 1",
+                SfenError::TooManyPieces,
+            ),
+        ] {
+            assert_eq!(Err(err), super::validate(sfen), "{sfen}");
+        }
+        // 成った歩は二歩にならない
+        assert_eq!(Ok(()), super::validate("4k4/9/9/9/9/4+P4/9/4P4/4K4 b - 1"));
+    }
+
+    #[test]
+    fn reset_to() {
+        let sfen = "ln1g3nl/1r1sk1gs1/p1ppppb1p/1p4pp1/9/2P3P2/PP1PPP2P/1BG3SR1/LNS1KG1NL w - 24";
+        let mut fresh = Position::from_sfen(sfen).expect("failed to parse");
+        let m = fresh.legal_moves_sorted()[0];
+        let mut pos = Position::default();
+        for _ in 0..50 {
+            let m = pos.legal_moves_sorted()[0];
+            pos.do_move(m);
+        }
+        pos.reset_to(sfen, &[m]).expect("failed to parse");
+        fresh.do_move(m);
+        assert_eq!(fresh.keys(), pos.keys());
+        assert_eq!(fresh.board_array(), pos.board_array());
+        assert_eq!(fresh.ply(), pos.ply());
+        assert_eq!(fresh.last_move(), pos.last_move());
+        assert!(pos.captures_history().is_empty());
+
+        let key = pos.key();
+        assert_eq!(
+            Err(SfenError::InvalidSide),
+            pos.reset_to("9/9/9/9/9/9/9/9/9 x - 1", &[])
+        );
+        assert_eq!(key, pos.key());
+
+        pos.reset_to(
+            "sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1",
+            &[],
+        )
+        .expect("failed to parse");
+        assert_eq!(Position::default().keys(), pos.keys());
+        assert_eq!(None, pos.last_move());
+    }
+
+    #[test]
+    fn invalid_sfen() {
+        for (sfen, err) in [
+            ("", SfenError::InvalidFields),
+            ("9/9/9/9/9/9/9/9/9 b -", SfenError::InvalidFields),
+            ("9/9/9/9/9/9/9/9 b - 1", SfenError::InvalidBoard),
+            ("9/9/9/9/9/9/9/9/8 b - 1", SfenError::InvalidBoard),
+            ("9/9/9/9/9/9/9/9/91 b - 1", SfenError::InvalidBoard),
+            ("9/9/9/9/9/9/9/9/8X b - 1", SfenError::InvalidBoard),
+            ("9/9/9/9/9/9/9/9/8+G b - 1", SfenError::InvalidBoard),
+            ("9/9/9/9/9/9/9/9/8+ b - 1", SfenError::InvalidBoard),
+            ("9/9/9/9/9/9/9/9/9 x - 1", SfenError::InvalidSide),
+            ("9/9/9/9/9/9/9/9/9 b 2 1", SfenError::InvalidHand),
+            ("9/9/9/9/9/9/9/9/9 b K 1", SfenError::InvalidHand),
+            ("9/9/9/9/9/9/9/9/9 b 19P 1", SfenError::InvalidHand),
+            ("9/9/9/9/9/9/9/9/9 b - 0", SfenError::InvalidPly),
+            ("9/9/9/9/9/9/9/9/9 b - x", SfenError::InvalidPly),
+        ] {
+            assert_eq!(Some(err), Position::from_sfen(sfen).err(), "{sfen}");
+        }
+    }
+
+    #[test]
+    fn sfen_round_trip_random_games() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(154);
+        for _ in 0..20 {
+            let mut pos = Position::default();
+            for _ in 0..200 {
+                let sfen = pos.to_sfen();
+                let parsed = Position::from_sfen(&sfen).expect("failed to parse");
+                assert_eq!(pos.board_array(), parsed.board_array(), "{sfen}");
+                for c in Color::all() {
+                    assert_eq!(pos.hand(c), parsed.hand(c), "{sfen}");
+                }
+                assert_eq!(pos.side_to_move(), parsed.side_to_move(), "{sfen}");
+                assert_eq!(pos.ply(), parsed.ply(), "{sfen}");
+                assert_eq!(pos.keys(), parsed.keys(), "{sfen}");
+                assert_eq!(sfen, parsed.to_sfen());
+
+                let moves = pos.legal_moves();
+                if moves.is_empty() {
+                    break;
+                }
+                pos.do_move(moves[rng.gen_range(0..moves.len())]);
+            }
+        }
+    }
+
+    #[test]
+    fn canonical_hash() {
+        // zobrist の乱数表に依らない固定値
+        assert_eq!(0x301c_f115_cbd4_fdd1, Position::default().canonical_hash());
+
+        // 手数は無視される
+        let pos =
+            Position::from_sfen("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 99")
+                .expect("failed to parse");
+        assert_eq!(Position::default().canonical_hash(), pos.canonical_hash());
+
+        // 手番や持ち駒が違えば異なる
+        for sfen in [
+            "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b P 1",
+            "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b p 1",
+        ] {
+            let pos = Position::from_sfen(sfen).expect("failed to parse");
+            assert_ne!(Position::default().canonical_hash(), pos.canonical_hash());
+        }
+    }
+
+    #[test]
+    fn from_usi_position_command() {
+        let (pos, moves) = Position::from_usi_position_command("position startpos moves 7g7f")
+            .expect("failed to parse");
+        assert_eq!(Position::default().keys(), pos.keys());
+        assert_eq!(
+            vec![Move::Normal {
+                from: Square::SQ_7G,
+                to: Square::SQ_7F,
+                promote: false,
+            }],
+            moves
+        );
+
+        let sfen = "lnsgkgsnl/1r5b1/ppppppppp/9/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL w - 2";
+        let (mut pos, moves) = Position::from_usi_position_command(&format!(
+            "position sfen {sfen} moves 3c3d 8h2b+ 3a2b B*4e"
+        ))
+        .expect("failed to parse");
+        assert_eq!(sfen, pos.to_sfen());
+        assert_eq!(4, moves.len());
+        moves.iter().for_each(|&m| pos.do_move(m));
+        assert_eq!(
+            "lnsgkg1nl/1r5s1/pppppp1pp/6p2/5B3/2P6/PP1PPPPPP/7R1/LNSGKGSNL w b 6",
+            pos.to_sfen()
+        );
+
+        let (pos, moves) = Position::from_usi_position_command(&format!("position sfen {sfen}"))
+            .expect("failed to parse");
+        assert_eq!(sfen, pos.to_sfen());
+        assert!(moves.is_empty());
+        let (_, moves) = Position::from_usi_position_command("position startpos moves")
+            .expect("failed to parse");
+        assert!(moves.is_empty());
+
+        for (line, err) in [
+            ("startpos", PositionCommandError::InvalidCommand),
+            ("position", PositionCommandError::InvalidCommand),
+            (
+                "position startpos 7g7f",
+                PositionCommandError::InvalidCommand,
+            ),
+            (
+                "position sfen 9/9/9 b - moves 7g7f",
+                PositionCommandError::Sfen(SfenError::InvalidBoard),
+            ),
+            (
+                "position sfen 9/9/9 b -",
+                PositionCommandError::Sfen(SfenError::InvalidFields),
+            ),
+            (
+                "position startpos moves 7g7f 7g7f",
+                PositionCommandError::Move(1, PlayError::IllegalMove),
+            ),
+            (
+                "position startpos moves 7g7x",
+                PositionCommandError::Move(0, PlayError::InvalidUsi),
+            ),
+        ] {
+            assert_eq!(
+                Some(err),
+                Position::from_usi_position_command(line).err(),
+                "{line}"
+            );
+        }
+    }
+}
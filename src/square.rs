@@ -0,0 +1,129 @@
+use shogi_core::{Color, Square};
+
+/// Creates a [`Square`] from `file` and `rank` (both `1..=9`), usable in const contexts.
+///
+/// Panics if `file` or `rank` is out of range, which is a compile error in const contexts.
+pub const fn square(file: u8, rank: u8) -> Square {
+    match Square::new(file, rank) {
+        Some(sq) => sq,
+        None => panic!("square out of range"),
+    }
+}
+
+const fn all_squares(rev: bool) -> [Square; Square::NUM] {
+    let mut squares = [Square::SQ_1A; Square::NUM];
+    let mut i = 0;
+    while i < Square::NUM {
+        let j = if rev { Square::NUM - 1 - i } else { i };
+        squares[i] = square(j as u8 / 9 + 1, j as u8 % 9 + 1);
+        i += 1;
+    }
+    squares
+}
+
+/// Extension methods for [`Square`].
+pub trait SquareExt {
+    /// All 81 squares in the order of [`Square::all`]: 1a, 1b, ..., 1i, 2a, ..., 9i.
+    const ALL: [Square; Square::NUM];
+    /// All 81 squares in the reverse order of [`SquareExt::ALL`]: 9i, 9h, ..., 9a, 8i, ..., 1a.
+    const ALL_REV: [Square; Square::NUM];
+
+    /// Returns the square as seen from `c`: itself for Black,
+    /// and rotated by 180 degrees (both the file and the rank mirrored) for White.
+    ///
+    /// A piece-square table written for Black can be looked up for White with this.
+    fn relative(self, c: Color) -> Square;
+    /// Returns the square of `index` in the SFEN reading order,
+    /// where 9a is 0, 1a is 8, 9b is 9 and 1i is 80.
+    fn from_usi_index(index: usize) -> Option<Square>;
+    /// Returns the index of the square in the SFEN reading order. See [`SquareExt::from_usi_index`].
+    fn to_usi_index(self) -> usize;
+}
+
+impl SquareExt for Square {
+    const ALL: [Square; Square::NUM] = all_squares(false);
+    const ALL_REV: [Square; Square::NUM] = all_squares(true);
+
+    #[inline(always)]
+    fn relative(self, c: Color) -> Square {
+        match c {
+            Color::Black => self,
+            Color::White => self.flip(),
+        }
+    }
+    fn from_usi_index(index: usize) -> Option<Square> {
+        if index >= Square::NUM {
+            return None;
+        }
+        Square::new(9 - (index % 9) as u8, (index / 9) as u8 + 1)
+    }
+    fn to_usi_index(self) -> usize {
+        (self.rank() as usize - 1) * 9 + (9 - self.file() as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn const_square() {
+        const CORNERS: [Square; 4] = [square(1, 1), square(9, 1), square(1, 9), square(9, 9)];
+        assert_eq!(
+            [Square::SQ_1A, Square::SQ_9A, Square::SQ_1I, Square::SQ_9I],
+            CORNERS
+        );
+        for sq in Square::all() {
+            let sq2 = square(sq.file(), sq.rank());
+            assert_eq!(sq, sq2);
+            assert_eq!(sq.index(), sq2.index());
+        }
+    }
+
+    #[test]
+    fn all() {
+        assert_eq!(81, Square::ALL.len());
+        assert_eq!(81, Square::ALL_REV.len());
+        assert_eq!(Square::SQ_1A, Square::ALL[0]);
+        assert_eq!(Square::SQ_1B, Square::ALL[1]);
+        assert_eq!(Square::SQ_2A, Square::ALL[9]);
+        assert_eq!(Square::SQ_9I, Square::ALL[80]);
+        assert_eq!(Square::SQ_9I, Square::ALL_REV[0]);
+        assert_eq!(Square::SQ_1A, Square::ALL_REV[80]);
+        assert!(Square::ALL.iter().copied().eq(Square::all()));
+        assert!(Square::ALL_REV.iter().rev().eq(Square::ALL.iter()));
+    }
+
+    #[test]
+    fn relative() {
+        assert_eq!(Square::SQ_1A, Square::SQ_1A.relative(Color::Black));
+        assert_eq!(Square::SQ_9I, Square::SQ_1A.relative(Color::White));
+        assert_eq!(Square::SQ_5E, Square::SQ_5E.relative(Color::White));
+        for sq in Square::all() {
+            let rel = sq.relative(Color::White);
+            assert_eq!((10 - sq.file(), 10 - sq.rank()), (rel.file(), rel.rank()));
+            assert_eq!(sq.relative_rank(Color::White), rel.rank());
+            assert_eq!(sq, rel.relative(Color::White));
+        }
+    }
+
+    #[test]
+    fn usi_index() {
+        assert_eq!(0, Square::SQ_9A.to_usi_index());
+        assert_eq!(8, Square::SQ_1A.to_usi_index());
+        assert_eq!(9, Square::SQ_9B.to_usi_index());
+        assert_eq!(40, Square::SQ_5E.to_usi_index());
+        assert_eq!(80, Square::SQ_1I.to_usi_index());
+        assert_eq!(Some(Square::SQ_7C), Square::from_usi_index(20));
+        assert_eq!(None, Square::from_usi_index(81));
+        for sq in Square::all() {
+            assert_eq!(Some(sq), Square::from_usi_index(sq.to_usi_index()));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "square out of range")]
+    fn out_of_range() {
+        square(10, 1);
+    }
+}
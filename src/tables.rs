@@ -230,18 +230,51 @@ pub(crate) static RELATIVE_RANKS: Lazy<[[usize; Color::NUM]; Square::NUM]> = Laz
     ranks
 });
 
-pub(crate) static PROMOTABLE: Lazy<[[bool; Color::NUM]; Square::NUM]> = Lazy::new(|| {
-    let mut table = [[false; Color::NUM]; Square::NUM];
-    for sq in Square::all() {
-        for c in Color::all() {
-            if RELATIVE_RANKS[sq.array_index()][c.array_index()] <= 3 {
-                table[sq.array_index()][c.array_index()] = true;
+pub(crate) static PROMOTABLE: Lazy<[[[bool; Color::NUM]; Square::NUM]; 10]> = Lazy::new(|| {
+    let mut table = [[[false; Color::NUM]; Square::NUM]; 10];
+    for (ranks, t) in table.iter_mut().enumerate() {
+        for sq in Square::all() {
+            for c in Color::all() {
+                if RELATIVE_RANKS[sq.array_index()][c.array_index()] <= ranks {
+                    t[sq.array_index()][c.array_index()] = true;
+                }
             }
         }
     }
     table
 });
 
+/// Squares on the files without a bit in the index, which is a mask of files (bit `file - 1`).
+pub(crate) static VACANT_FILES: Lazy<[Bitboard; 1 << 9]> = Lazy::new(|| {
+    let mut table = [Bitboard::empty(); 1 << 9];
+    for (mask, bb) in table.iter_mut().enumerate() {
+        for sq in Square::all() {
+            if mask & (1 << (sq.file() - 1)) == 0 {
+                *bb |= Bitboard::single(sq);
+            }
+        }
+    }
+    table
+});
+
+/// Squares on each file, indexed by `file - 1`.
+pub(crate) static FILES: Lazy<[Bitboard; 9]> = Lazy::new(|| {
+    let mut table = [Bitboard::empty(); 9];
+    for sq in Square::all() {
+        table[sq.file() as usize - 1] |= Bitboard::single(sq);
+    }
+    table
+});
+
+/// Squares on each rank, indexed by `rank - 1`.
+pub(crate) static RANKS: Lazy<[Bitboard; 9]> = Lazy::new(|| {
+    let mut table = [Bitboard::empty(); 9];
+    for sq in Square::all() {
+        table[sq.rank() as usize - 1] |= Bitboard::single(sq);
+    }
+    table
+});
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -329,6 +362,42 @@ mod tests {
         }
     }
 
+    #[test]
+    fn sliding_attacks_match_ray_walking() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(110);
+        for _ in 0..100 {
+            let occ = Square::all()
+                .filter(|_| rng.gen_bool(0.25))
+                .fold(Bitboard::empty(), |bb, sq| bb | Bitboard::single(sq));
+            for sq in Square::all() {
+                let ray = |deltas: &[Delta]| {
+                    deltas.iter().fold(Bitboard::empty(), |bb, &d| {
+                        bb | super::sliding_attack(sq, occ, d)
+                    })
+                };
+                assert_eq!(
+                    ray(&[Delta::NE, Delta::SE, Delta::SW, Delta::NW]),
+                    ATTACK_TABLE.ka.attack(sq, &occ)
+                );
+                assert_eq!(
+                    ray(&[Delta::N, Delta::E, Delta::S, Delta::W]),
+                    ATTACK_TABLE.hi.attack(sq, &occ)
+                );
+                assert_eq!(
+                    ray(&[Delta::N]),
+                    ATTACK_TABLE.ky.attack(sq, Color::Black, &occ)
+                );
+                assert_eq!(
+                    ray(&[Delta::S]),
+                    ATTACK_TABLE.ky.attack(sq, Color::White, &occ)
+                );
+            }
+        }
+    }
+
     #[test]
     fn sliding_attack() {
         {
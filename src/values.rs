@@ -0,0 +1,51 @@
+use shogi_core::PieceKind;
+
+/// Material values of the pieces, used by [`Position::see`](crate::Position::see).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PieceValues([i32; PieceKind::NUM]);
+
+impl PieceValues {
+    #[rustfmt::skip]
+    pub const DEFAULT: PieceValues = PieceValues([
+        90, 315, 405, 495, 540, 855, 990, 15000,
+        540, 540, 540, 540, 945, 1395,
+    ]);
+
+    /// Creates values indexed by [`PieceKind::array_index`].
+    pub const fn new(values: [i32; PieceKind::NUM]) -> Self {
+        Self(values)
+    }
+    #[inline(always)]
+    pub fn value(&self, pk: PieceKind) -> i32 {
+        self.0[pk.array_index()]
+    }
+    /// Returns the value gained by promoting `pk`, or 0 if it can't promote.
+    pub fn promotion_gain(&self, pk: PieceKind) -> i32 {
+        pk.promote()
+            .map_or(0, |ppk| self.value(ppk) - self.value(pk))
+    }
+}
+
+impl Default for PieceValues {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values() {
+        let values = PieceValues::default();
+        assert!(values.value(PieceKind::Pawn) < values.value(PieceKind::Lance));
+        assert!(values.value(PieceKind::Bishop) < values.value(PieceKind::Rook));
+        assert_eq!(
+            values.value(PieceKind::ProRook) - values.value(PieceKind::Rook),
+            values.promotion_gain(PieceKind::Rook)
+        );
+        assert_eq!(0, values.promotion_gain(PieceKind::Gold));
+        assert_eq!(0, values.promotion_gain(PieceKind::ProPawn));
+    }
+}
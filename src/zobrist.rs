@@ -1,3 +1,4 @@
+use crate::SquareExt;
 use once_cell::sync::Lazy;
 use rand::rngs::StdRng;
 use rand::Rng;
@@ -5,7 +6,7 @@ use rand::SeedableRng;
 use shogi_core::{Color, Hand, Piece, PieceKind, Square};
 use std::ops;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Key(u64);
 
 impl Key {
@@ -67,7 +68,7 @@ pub static ZOBRIST_TABLE: Lazy<ZobristTable> = Lazy::new(|| {
     let mut board = [[[Key::ZERO; PieceKind::NUM]; Color::NUM]; Square::NUM];
     let mut hands = [[[Key::ZERO; ZobristTable::MAX_HAND_NUM]; 8]; Color::NUM];
     let mut rng = StdRng::seed_from_u64(2022);
-    for sq in Square::all() {
+    for sq in Square::ALL {
         for c in Color::all() {
             for pk in PieceKind::all() {
                 board[sq.array_index()][c.array_index()][pk.array_index()] =
@@ -77,8 +78,8 @@ pub static ZOBRIST_TABLE: Lazy<ZobristTable> = Lazy::new(|| {
     }
     for c in Color::all() {
         for pk in Hand::all_hand_pieces() {
-            for num in 0..ZobristTable::MAX_HAND_NUM {
-                hands[c.array_index()][pk.array_index()][num] = Key(rng.gen()) & !Key::COLOR;
+            for key in hands[c.array_index()][pk.array_index()].iter_mut() {
+                *key = Key(rng.gen()) & !Key::COLOR;
             }
         }
     }
@@ -192,7 +193,7 @@ mod tests {
         let mut hs = HashSet::new();
         let mut pos = Position::default();
         for i in 0..100 {
-            let moves = pos.legal_moves().into_iter().collect::<Vec<_>>();
+            let moves = pos.legal_moves_sorted().into_iter().collect::<Vec<_>>();
             let choice = moves[(i * 100) % moves.len()];
             pos.do_move(choice);
             let key = pos.key();
@@ -236,9 +237,11 @@ mod tests {
                     promote: false,
                 },
             ];
+            let key = Position::default().keys_after(&moves);
             moves.iter().for_each(|&m| {
                 pos.do_move(m);
             });
+            assert_eq!(key, pos.key());
             pos.key()
         };
         let key1 = {
@@ -261,9 +264,11 @@ mod tests {
                     promote: false,
                 },
             ];
+            let key = Position::default().keys_after(&moves);
             moves.iter().for_each(|&m| {
                 pos.do_move(m);
             });
+            assert_eq!(key, pos.key());
             pos.key()
         };
         assert_eq!(key0, key1);